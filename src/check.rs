@@ -1,7 +1,29 @@
 use ansi_term::Colour::Green;
 use ansi_term::Colour::Red;
+use ansi_term::Colour::Yellow;
+use dialoguer::Confirm;
+use lazy_static::lazy_static;
 use log::trace;
-use std::process::Command;
+use regex::Regex;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use toml::Value;
+
+use crate::bundle;
+use crate::config;
+use crate::parse;
+use crate::ports;
+use crate::shell;
 
 use dns_lookup::lookup_host;
 
@@ -10,83 +32,803 @@ use failure::{bail, Error};
 const APPS: &[&str] = &["git", "nix", "docker", "docker-compose"];
 const HOSTNAME: &str = "es-dev.precisionnutrition.com";
 
-/// Runs all checks
+/// How long a check may run before it is reported as timed out
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// ssh gives up connecting after 5 seconds, leave time for the handshake
+const GITHUB_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Pass,
+    Fail,
+    /// reported by doctor, never stops a command
+    Warn,
+    /// the check did not finish in time, so the state is unknown
+    TimedOut,
+}
+
+/// Result of running a check
+#[derive(Debug, Serialize)]
+pub struct Outcome {
+    pub id: String,
+    pub status: Status,
+    pub message: String,
+    /// what to do by hand when the check does not pass
+    pub remediation: Option<String>,
+}
+
+impl Outcome {
+    const fn pass(message: String) -> Self {
+        Self {
+            id: String::new(),
+            status: Status::Pass,
+            message,
+            remediation: None,
+        }
+    }
+
+    const fn fail(message: String, remediation: String) -> Self {
+        Self {
+            id: String::new(),
+            status: Status::Fail,
+            message,
+            remediation: Some(remediation),
+        }
+    }
+
+    const fn warn(message: String, remediation: String) -> Self {
+        Self {
+            id: String::new(),
+            status: Status::Warn,
+            message,
+            remediation: Some(remediation),
+        }
+    }
+
+    fn timed_out(check: &Check) -> Self {
+        Self {
+            id: check.id.clone(),
+            status: Status::TimedOut,
+            message: format!("{} timed out after {}s", check.id, check.timeout.as_secs()),
+            remediation: Some(String::from(
+                "run pndev doctor again, a slow network or a stuck docker daemon can cause this",
+            )),
+        }
+    }
+}
+
+type Probe = Box<dyn Fn() -> Outcome + Send + Sync>;
+type Fixer = Box<dyn Fn() -> Result<(), Error> + Send + Sync>;
+
+/// Something about the environment pndev depends on
+pub struct Check {
+    /// stable name in `doctor --json`, e.g. `binary:git`
+    id: String,
+    /// failing required checks stop every command, the others only show in doctor
+    required: bool,
+    probe: Probe,
+    /// confirmation prompt and automated fix, offered by `doctor --fix`
+    fix: Option<(String, Fixer)>,
+    timeout: Duration,
+}
+
+impl Check {
+    fn new<F>(id: String, probe: F) -> Self
+    where
+        F: Fn() -> Outcome + Send + Sync + 'static,
+    {
+        Self {
+            id,
+            required: false,
+            probe: Box::new(probe),
+            fix: None,
+            timeout: CHECK_TIMEOUT,
+        }
+    }
+
+    const fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    const fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn fix<F>(mut self, prompt: String, fix: F) -> Self
+    where
+        F: Fn() -> Result<(), Error> + Send + Sync + 'static,
+    {
+        self.fix = Some((prompt, Box::new(fix)));
+        self
+    }
+
+    pub fn run(&self) -> Outcome {
+        Outcome {
+            id: self.id.clone(),
+            ..(self.probe)()
+        }
+    }
+}
+
+/// The checks for the project in `dir`: pndev's own plus the `[requirements]` of its pndev.toml
+///
+/// ```toml
+/// [requirements]
+/// binaries = { node = "14.17", yarn = "1.22", psql = "*" }
+/// hosts = ["wp-dev.precisionnutrition.com"]
+/// env = ["AWS_PROFILE"]
+/// files = ["config/master.key"]
+/// ```
+pub fn registry(dir: &Path) -> Result<Vec<Check>, Error> {
+    let mut checks = builtin();
+
+    if dir.join("pndev.toml").exists() {
+        if let Some(requirements) = parse::config_at(dir)?.get("requirements") {
+            checks.extend(requirements_checks(dir, requirements)?);
+        }
+    }
+
+    Ok(checks)
+}
+
+fn builtin() -> Vec<Check> {
+    let minimums = minimum_versions();
+    let mut checks: Vec<Check> = APPS
+        .iter()
+        .map(|&app| tool_check(app, minimums.get(app).cloned()).required())
+        .collect();
+
+    checks.push(docker_daemon_check());
+    checks.push(ports_check());
+    checks.push(config_check());
+    checks.push(catalog_check());
+    checks.push(host_check(HOSTNAME).required());
+    checks.push(
+        Check::new(String::from("github"), || {
+            if check_github() {
+                Outcome::pass(String::from("github ssh access allowed"))
+            } else {
+                Outcome::fail(
+                    String::from("github ssh access not allowed"),
+                    String::from("add your ssh key to https://github.com/settings/keys and load it with `ssh-add <path to keyfile>`"),
+                )
+            }
+        })
+        .timeout(GITHUB_TIMEOUT),
+    );
+    checks.push(anonymize_creds_check());
+
+    checks
+}
+
+fn requirements_checks(dir: &Path, requirements: &Value) -> Result<Vec<Check>, Error> {
+    let mut checks = vec![];
+
+    match requirements.get("binaries") {
+        // names only, any version
+        Some(Value::Array(binaries)) => {
+            for binary in binaries.iter().filter_map(Value::as_str) {
+                checks.push(binary_check(binary, None).required());
+            }
+        }
+        Some(Value::Table(binaries)) => {
+            for (binary, version) in binaries {
+                let minimum = match version.as_str() {
+                    Some("*" | "") => None,
+                    Some(version) => Some(version.to_owned()),
+                    None => bail!("Invalid version for {} in pndev.toml requirements", binary),
+                };
+                checks.push(binary_check(binary, minimum).required());
+            }
+        }
+        Some(_) => bail!("Invalid binaries in pndev.toml requirements"),
+        None => {}
+    }
+
+    for host in strings(requirements, "hosts")? {
+        checks.push(host_check(&host).required());
+    }
+
+    for var in strings(requirements, "env")? {
+        checks.push(
+            Check::new(format!("env:{var}"), move || {
+                if std::env::var_os(&var).is_some() {
+                    Outcome::pass(format!("{var} is set"))
+                } else {
+                    Outcome::fail(
+                        format!("{var} is not set"),
+                        format!("export {var} in your shell profile"),
+                    )
+                }
+            })
+            .required(),
+        );
+    }
+
+    for file in strings(requirements, "files")? {
+        let path = dir.join(&file);
+        checks.push(
+            Check::new(format!("file:{file}"), move || {
+                if path.exists() {
+                    Outcome::pass(format!("{file} present"))
+                } else {
+                    Outcome::fail(
+                        format!("{file} missing"),
+                        format!("create {}", path.display()),
+                    )
+                }
+            })
+            .required(),
+        );
+    }
+
+    Ok(checks)
+}
+
+/// A list of strings from the requirements table
+fn strings(requirements: &Value, key: &str) -> Result<Vec<String>, Error> {
+    match requirements.get(key) {
+        Some(Value::Array(values)) => Ok(values
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_owned)
+            .collect()),
+        Some(_) => bail!("Invalid {} in pndev.toml requirements", key),
+        None => Ok(vec![]),
+    }
+}
+
+/// Runs `checks` concurrently, each one given up on after its timeout.
+/// Outcomes come back in the order of the checks.
+fn run_all(checks: Vec<Check>) -> Vec<(Arc<Check>, Outcome)> {
+    let checks: Vec<Arc<Check>> = checks.into_iter().map(Arc::new).collect();
+    let started = Instant::now();
+    let (sender, receiver) = mpsc::channel();
+
+    for (index, check) in checks.iter().enumerate() {
+        let check = Arc::clone(check);
+        let sender = sender.clone();
+
+        // a timed out check keeps its thread, nobody listens to it anymore
+        thread::spawn(move || sender.send((index, check.run())).ok());
+    }
+    drop(sender);
+
+    let mut outcomes: Vec<Option<Outcome>> = checks.iter().map(|_| None).collect();
+
+    loop {
+        let deadline = checks
+            .iter()
+            .zip(&outcomes)
+            .filter(|(_, outcome)| outcome.is_none())
+            .map(|(check, _)| started + check.timeout)
+            .min();
+
+        let deadline = match deadline {
+            Some(deadline) => deadline,
+            None => break,
+        };
+
+        match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok((index, outcome)) => outcomes[index] = Some(outcome),
+            Err(RecvTimeoutError::Timeout) => {
+                for (check, outcome) in checks.iter().zip(outcomes.iter_mut()) {
+                    if outcome.is_none() && started.elapsed() >= check.timeout {
+                        trace!("check {} timed out", check.id);
+                        *outcome = Some(Outcome::timed_out(check));
+                    }
+                }
+            }
+            // only when a check panicked, its outcome is filled in below
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    checks
+        .into_iter()
+        .zip(outcomes)
+        .map(|(check, outcome)| {
+            let outcome = outcome.unwrap_or_else(|| Outcome {
+                id: check.id.clone(),
+                ..Outcome::fail(
+                    format!("{} crashed", check.id),
+                    String::from("run pndev -vvvv doctor and report the output"),
+                )
+            });
+
+            (check, outcome)
+        })
+        .collect()
+}
+
+/// Runs the required checks, before every command
 pub fn all() -> Result<(), Error> {
     trace!("pn_doctor called");
-    for &app in APPS {
-        if !check_app_installed(app) {
-            bail!("{} not installed, run pndev doctor for help", app);
+
+    let required = registry(Path::new("."))?
+        .into_iter()
+        .filter(|check| check.required)
+        .collect();
+
+    for (_, outcome) in run_all(required) {
+        match outcome.status {
+            Status::Fail => bail!("{}, run pndev doctor for help", outcome.message),
+            // unknown is not broken, the command itself will tell
+            Status::TimedOut => trace!("{}", outcome.message),
+            Status::Pass | Status::Warn => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Outcome of every check, as `doctor --json` prints them
+pub fn report() -> Result<String, Error> {
+    let outcomes: Vec<Outcome> = run_all(registry(Path::new("."))?)
+        .into_iter()
+        .map(|(_, outcome)| outcome)
+        .collect();
+
+    Ok(serde_json::to_string_pretty(&outcomes)?)
+}
+
+/// Prints the state of every check, with `fix` offers to fix what pndev can fix
+/// and prints instructions for the rest
+pub fn doctor(fix: bool, json: bool, bundle: bool) -> Result<(), Error> {
+    trace!("check_all called");
+
+    if json {
+        println!("{}", report()?);
+        return Ok(());
+    }
+
+    if bundle {
+        let path = bundle::write(&report()?)?;
+        println!(
+            "{} {} written, attach it to your support request",
+            Green.paint("✓"),
+            path.display()
+        );
+        return Ok(());
+    }
+
+    let mut fixable = false;
+
+    for (check, outcome) in run_all(registry(Path::new("."))?) {
+        match outcome.status {
+            Status::Pass => {
+                println!("{} {}", Green.paint("✓"), outcome.message);
+                continue;
+            }
+            Status::Fail => println!("{} {}", Red.paint("✗"), outcome.message),
+            Status::Warn => println!("{} {}", Yellow.paint("⚠"), outcome.message),
+            Status::TimedOut => println!("{} {}", Yellow.paint("⏱"), outcome.message),
+        }
+
+        // nothing to fix when the state is unknown
+        let fixer = check
+            .fix
+            .as_ref()
+            .filter(|_| outcome.status != Status::TimedOut);
+
+        if let Some((prompt, apply)) = fixer {
+            if apply_fix(fix, prompt, apply)? {
+                continue;
+            }
+            fixable = true;
+        }
+
+        if let Some(remediation) = &outcome.remediation {
+            instructions(remediation);
         }
     }
 
-    if !check_host() {
-        bail!("es-dev not configured, run pndev doctor for help");
+    if fixable && !fix {
+        println!("\nrun `pndev doctor --fix` to fix some of these automatically");
     }
 
     Ok(())
 }
 
-pub fn doctor() -> Result<(), Error> {
-    trace!("check_all called");
+/// With `fix`, asks before running `apply`. Returns whether the problem got fixed.
+fn apply_fix(fix: bool, prompt: &str, apply: &Fixer) -> Result<bool, Error> {
+    if !fix || !Confirm::new().with_prompt(prompt).interact()? {
+        return Ok(false);
+    }
 
-    for &app in APPS {
-        if check_app_installed(app) {
-            println!("{} {} installed", Green.paint("✓"), app);
+    match apply() {
+        Ok(()) => {
+            println!("{} fixed", Green.paint("✓"));
+            Ok(true)
+        }
+        Err(error) => {
+            println!("{} fix failed: {}", Red.paint("✗"), error);
+            Ok(false)
+        }
+    }
+}
+
+fn instructions(text: &str) {
+    println!("  {} {}", Yellow.paint("Fix:"), text);
+}
+
+fn install_instructions(app: &str) -> String {
+    match app {
+        "git" => String::from("install git from https://git-scm.com/downloads"),
+        "nix" => String::from(
+            "curl -L https://nixos.org/nix/install | sh, see https://nixos.org/download.html",
+        ),
+        "docker" => String::from("install Docker from https://docs.docker.com/get-docker/"),
+        "docker-compose" => {
+            String::from("install docker-compose from https://docs.docker.com/compose/install/")
+        }
+        _ => format!("install {app}, or add it to the project's shell.nix"),
+    }
+}
+
+/// `[minimum_versions]` of the catalog's requirements.toml, empty until the catalog is cloned.
+/// `[ports]` in the config raises docker-compose to the version understanding its override.
+fn minimum_versions() -> BTreeMap<String, String> {
+    let config = configured();
+    let path = Path::new(&config.catalog_path()).join("requirements.toml");
+
+    let mut minimums: BTreeMap<String, String> = fs::read_to_string(path)
+        .ok()
+        .and_then(|content| toml::from_str::<Value>(&content).ok())
+        .and_then(|requirements| {
+            requirements
+                .get("minimum_versions")
+                .and_then(Value::as_table)
+                .map(|minimums| {
+                    minimums
+                        .iter()
+                        .filter_map(|(app, version)| {
+                            Some((app.clone(), version.as_str()?.to_owned()))
+                        })
+                        .collect()
+                })
+        })
+        .unwrap_or_default();
+
+    if !config.ports().is_empty() {
+        let compose = minimums.entry(String::from("docker-compose")).or_default();
+
+        if compare_versions(compose, ports::OVERRIDE_COMPOSE_VERSION).is_lt() {
+            *compose = ports::OVERRIDE_COMPOSE_VERSION.to_owned();
+        }
+    }
+
+    minimums
+}
+
+/// The docker CLI can be installed while the daemon is stopped
+fn docker_daemon_check() -> Check {
+    Check::new(String::from("docker-daemon"), || {
+        let running = Command::new("docker")
+            .args(["info"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+
+        if running {
+            Outcome::pass(String::from("docker daemon running"))
         } else {
-            println!("{} {} not installed", Red.paint("✗"), app);
+            Outcome::fail(
+                String::from("docker daemon not reachable"),
+                String::from("start Docker Desktop, or the docker service with `sudo systemctl start docker`"),
+            )
+        }
+    })
+}
+
+/// Host ports of the pndev services taken by other processes
+fn ports_check() -> Check {
+    Check::new(String::from("ports"), || {
+        if !Path::new(&configured().docker_compose_path()).exists() {
+            return Outcome::warn(
+                String::from("docker-compose.yml missing, ports not checked"),
+                String::from("clone the pndev catalog"),
+            );
+        }
+
+        match ports::conflicts() {
+            Ok(conflicts) if conflicts.is_empty() => {
+                Outcome::pass(String::from("pndev service ports available"))
+            }
+            Ok(conflicts) => Outcome::fail(
+                conflicts
+                    .iter()
+                    .map(ports::describe)
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                conflicts
+                    .iter()
+                    .map(ports::suggestion)
+                    .collect::<Vec<String>>()
+                    .join("; "),
+            ),
+            Err(error) => Outcome::warn(
+                format!("could not check ports: {error}"),
+                String::from("check the docker-compose.yml of the pndev catalog"),
+            ),
+        }
+    })
+}
+
+/// `binary` is installed, and at least `minimum` when given
+fn binary_check(binary: &str, minimum: Option<String>) -> Check {
+    let binary = binary.to_owned();
+
+    Check::new(format!("binary:{binary}"), move || {
+        binary_outcome(&binary, minimum.as_deref(), true)
+    })
+}
+
+/// Like `binary_check`, for the tools pndev relies on: an older version only warns
+/// instead of blocking every command
+fn tool_check(binary: &str, minimum: Option<String>) -> Check {
+    let binary = binary.to_owned();
+
+    Check::new(format!("binary:{binary}"), move || {
+        binary_outcome(&binary, minimum.as_deref(), false)
+    })
+}
+
+/// An older version than `minimum` fails when `strict`, and only warns otherwise
+fn binary_outcome(binary: &str, minimum: Option<&str>, strict: bool) -> Outcome {
+    let output = match Command::new(binary).args(["--version"]).output() {
+        Ok(output) => output,
+        Err(_) => {
+            return Outcome::fail(
+                format!("{binary} not installed"),
+                install_instructions(binary),
+            )
+        }
+    };
+
+    let minimum = match minimum {
+        Some(minimum) => minimum,
+        None => return Outcome::pass(format!("{binary} installed")),
+    };
+
+    let text = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    match extract_version(&text) {
+        Some(version) if compare_versions(&version, minimum) != Ordering::Less => {
+            Outcome::pass(format!("{binary} {version} installed"))
         }
+        Some(version) if strict => Outcome::fail(
+            format!("{binary} {version} installed, {minimum} or newer required"),
+            format!("upgrade {binary}, {}", install_instructions(binary)),
+        ),
+        Some(version) => Outcome::warn(
+            format!("{binary} {version} installed, {minimum} or newer recommended"),
+            format!("upgrade {binary}, {}", install_instructions(binary)),
+        ),
+        None => Outcome::warn(
+            format!("{binary} installed, could not tell if it is {minimum} or newer"),
+            format!("check that `{binary} --version` is at least {minimum}"),
+        ),
     }
+}
 
-    if check_host() {
-        println!(
-            "{} es-dev.precisionnutrition.com resolves",
-            Green.paint("✓")
-        );
-    } else {
-        println!(
-            "{} es-dev.precisionnutrition.com does not resolve",
-            Red.paint("✗")
-        );
+/// First version-looking number in a `--version` output, e.g. `2.3.16` in `nix (Nix) 2.3.16`
+pub fn extract_version(text: &str) -> Option<String> {
+    lazy_static! {
+        static ref VERSION: Regex = Regex::new(r"\d+(?:\.\d+)+").unwrap();
     }
 
-    if check_github() {
-        println!("{} github ssh access allowed", Green.paint("✓"));
+    VERSION.find(text).map(|found| found.as_str().to_owned())
+}
+
+/// Compares dotted versions numerically, missing parts count as 0
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parts = |version: &str| -> Vec<u64> {
+        version
+            .trim_start_matches('v')
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    let (a, b) = (parts(a), parts(b));
+
+    (0..a.len().max(b.len()))
+        .map(|i| a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)))
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+fn config_check() -> Check {
+    Check::new(String::from("config"), || {
+        let path = config::Config::file_path();
+
+        if Path::new(&path).exists() {
+            Outcome::pass(format!("{path} present"))
+        } else {
+            Outcome::fail(
+                format!("{path} missing"),
+                String::from("run `pndev doctor --fix` to create it with defaults"),
+            )
+        }
+    })
+    .fix(
+        format!("Create {} with defaults?", config::Config::file_path()),
+        || {
+            config::Config::new();
+            Ok(())
+        },
+    )
+}
+
+fn catalog_check() -> Check {
+    Check::new(String::from("catalog"), || {
+        let catalog = format!("{}/pndev", configured().repo_path());
+
+        if Path::new(&catalog).exists() {
+            Outcome::pass(String::from("pndev catalog cloned"))
+        } else {
+            Outcome::fail(
+                format!("pndev catalog not cloned in {catalog}"),
+                format!("git clone git@github.com:PrecisionNutrition/pndev.git {catalog}"),
+            )
+        }
+    })
+    .fix(
+        String::from("Clone the pndev catalog?"),
+        shell::Shell::check_setup,
+    )
+}
+
+/// The config file, without creating it like `Config::new` does
+fn configured() -> config::Config {
+    if Path::new(&config::Config::file_path()).exists() {
+        config::Config::new()
     } else {
-        println!("{} github ssh access not allowed", Red.paint("✗"));
+        config::Config::default()
     }
+}
 
-    if check_anonymize_creds() {
-        println!("{} ~/.pn_anonymize_creds present", Green.paint("✓"));
-    } else {
-        println!("{} ~/.pn_anonymize_creds missing", Red.paint("✗"));
+fn host_check(host: &str) -> Check {
+    let host = host.to_owned();
+    let prompt = format!("Add {host} to /etc/hosts (uses sudo)?");
+    let fix_host = host.clone();
+
+    Check::new(format!("host:{host}"), move || {
+        if check_host(&host) {
+            Outcome::pass(format!("{host} resolves"))
+        } else {
+            Outcome::fail(
+                format!("{host} does not resolve"),
+                format!("add `127.0.0.1 {host}` to /etc/hosts"),
+            )
+        }
+    })
+    .fix(prompt, move || add_host(&fix_host))
+}
+
+fn add_host(host: &str) -> Result<(), Error> {
+    let mut tee = Command::new("sudo")
+        .args(["tee", "-a", "/etc/hosts"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+
+    if let Some(mut stdin) = tee.stdin.take() {
+        writeln!(stdin, "\n127.0.0.1 {host}")?;
+    }
+
+    if !tee.wait()?.success() {
+        bail!("could not write to /etc/hosts");
     }
 
     Ok(())
 }
 
-fn check_anonymize_creds() -> bool {
+fn anonymize_creds_check() -> Check {
+    Check::new(String::from("anonymize-creds"), || {
+        if !check_anonymize_creds() {
+            Outcome::fail(
+                String::from("~/.pn_anonymize_creds missing"),
+                String::from("ask the team for the anonymized dump credentials and save them in ~/.pn_anonymize_creds"),
+            )
+        } else if !check_anonymize_creds_permissions() {
+            Outcome::fail(
+                String::from("~/.pn_anonymize_creds is readable by other users"),
+                String::from("chmod 600 ~/.pn_anonymize_creds"),
+            )
+        } else {
+            Outcome::pass(String::from("~/.pn_anonymize_creds present"))
+        }
+    })
+    .fix(
+        String::from("Make ~/.pn_anonymize_creds readable only by you?"),
+        fix_anonymize_creds_permissions,
+    )
+}
+
+fn anonymize_creds_path() -> PathBuf {
     let mut path = dirs::home_dir().unwrap();
     path.push(".pn_anonymize_creds");
 
-    path.exists()
+    path
+}
+
+/// The credentials must not be readable by group or others
+fn check_anonymize_creds_permissions() -> bool {
+    fs::metadata(anonymize_creds_path())
+        .map(|metadata| metadata.permissions().mode() & 0o077 == 0)
+        .unwrap_or(false)
 }
 
-fn check_app_installed(command: &str) -> bool {
-    let result = Command::new(command).args(["--version"]).output();
+fn fix_anonymize_creds_permissions() -> Result<(), Error> {
+    if !check_anonymize_creds() {
+        bail!("~/.pn_anonymize_creds does not exist");
+    }
+
+    fs::set_permissions(anonymize_creds_path(), fs::Permissions::from_mode(0o600))?;
 
-    result.is_ok()
+    Ok(())
+}
+
+fn check_anonymize_creds() -> bool {
+    anonymize_creds_path().exists()
 }
 
-fn check_host() -> bool {
-    lookup_host(HOSTNAME).is_ok()
+fn check_host(host: &str) -> bool {
+    lookup_host(host).is_ok()
 }
 
 fn check_github() -> bool {
-    let result = Command::new("ssh").args(["-T", "git@github.com"]).output();
+    // batch mode never prompts for a passphrase or host key, it fails instead
+    let result = Command::new("ssh")
+        .args([
+            "-T",
+            "-o",
+            "BatchMode=yes",
+            "-o",
+            "ConnectTimeout=5",
+            "git@github.com",
+        ])
+        .stdin(Stdio::null())
+        .output();
 
     match result {
         // ssh -T returns 1 even if auth works
-        Ok(output) => output.status.code().unwrap() % 255 == 1,
+        Ok(output) => output.status.code().is_some_and(|code| code % 255 == 1),
         Err(_) => false,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_versions() {
+        assert_eq!(
+            extract_version("nix (Nix) 2.3.16\n").as_deref(),
+            Some("2.3.16")
+        );
+        assert_eq!(
+            extract_version("Docker Compose version v2.20.2").as_deref(),
+            Some("2.20.2")
+        );
+        assert_eq!(extract_version("no version here"), None);
+    }
+
+    #[test]
+    fn compares_versions() {
+        assert_eq!(compare_versions("2.10.0", "2.9"), Ordering::Greater);
+        assert_eq!(compare_versions("1.22", "1.22.0"), Ordering::Equal);
+        assert_eq!(compare_versions("v14.17.1", "16"), Ordering::Less);
+    }
+}
@@ -1,5 +1,5 @@
 use failure::Error;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use log::info;
 use log::trace;
@@ -8,13 +8,25 @@ use failure::bail;
 
 use dirs::home_dir;
 
-use ansi_term::Colour::Red;
-use dialoguer::Confirm;
+use ansi_term::Colour::{Green, Red, Yellow};
+use dialoguer::{Confirm, Select};
+use regex::Regex;
 
 use crate::check;
+use crate::config;
+use crate::detect;
 use crate::git;
+use crate::health;
+use crate::hooks;
 use crate::parse;
+use crate::ports;
+use crate::procs;
+use crate::report;
+use crate::review;
 use crate::shell;
+use crate::ticket;
+use crate::watch;
+use crate::workspace;
 use crate::ResetType;
 
 const REPOS: &[&str] = &[
@@ -33,22 +45,62 @@ const APPS: &[&str] = &[
     "orchid",
 ];
 
+/// What to do with a repo that has uncommitted changes
+enum DirtyAction {
+    Stash,
+    Skip,
+}
+
+/// Short names accepted by --repos
+const ALIASES: &[(&str, &str)] = &[("es", "eternal-sledgehammer"), ("cj", "crown-jewel")];
+
 #[derive(Debug)]
 pub struct Command {
     name: Option<String>,
+    repos: Vec<String>,
+    branch_name: Option<String>,
     arguments: Vec<String>,
     pr: Option<String>,
     all: bool,
     docker_only: bool,
+    no_docker: bool,
+    delete: bool,
+    worktree: bool,
+    stash: bool,
+    skip_dirty: bool,
+    clone_options: git::CloneOptions,
+    bootstrap: bool,
+    junit: Option<String>,
+    tmux: bool,
+    open: bool,
+    globs: Vec<String>,
 }
 
 impl Command {
     pub const fn new() -> Self {
         Self {
             name: None,
+            repos: vec![],
+            branch_name: None,
             pr: None,
             all: false,
             docker_only: false,
+            no_docker: false,
+            delete: false,
+            worktree: false,
+            stash: false,
+            skip_dirty: false,
+            clone_options: git::CloneOptions {
+                depth: None,
+                filter: None,
+                cache: None,
+                https: None,
+            },
+            bootstrap: false,
+            junit: None,
+            tmux: false,
+            open: false,
+            globs: vec![],
             arguments: vec![],
         }
     }
@@ -61,11 +113,18 @@ impl Command {
         Ok(())
     }
 
-    pub fn start(docker_only: bool) -> Result<(), Error> {
+    pub fn start(docker_only: bool, explain: bool, open: bool) -> Result<(), Error> {
         trace!("start command");
 
+        if explain {
+            Self::new()._explain_start()?;
+
+            return Ok(());
+        }
+
         Self::new()
             .docker_only(docker_only)
+            .open(open)
             .check()?
             ._up()?
             ._start()?;
@@ -75,6 +134,64 @@ impl Command {
         Ok(())
     }
 
+    pub fn start_apps(
+        apps: Vec<String>,
+        stack: Option<String>,
+        tmux: bool,
+        open: bool,
+    ) -> Result<(), Error> {
+        trace!("start apps command");
+
+        let apps = match stack {
+            Some(stack) => match config::Config::new().stack(&stack) {
+                Some(apps) => apps.to_vec(),
+                None => bail!("Stack {} not found in ~/.pndev_config.toml", stack),
+            },
+            None => apps,
+        };
+
+        Self::new()
+            .repos(apps)
+            .tmux(tmux)
+            .open(open)
+            .check()?
+            ._up()?
+            ._start_apps()?;
+
+        trace!("start apps command done");
+
+        Ok(())
+    }
+
+    pub fn test(paths: Vec<String>, junit: Option<String>) -> Result<(), Error> {
+        trace!("test command");
+
+        Self::new()
+            .arguments(paths)
+            .junit(junit)
+            .check()?
+            ._up()?
+            ._test()?;
+
+        trace!("test command done");
+
+        Ok(())
+    }
+
+    pub fn watch(task: String, globs: Vec<String>, arguments: Vec<String>) -> Result<(), Error> {
+        trace!("watch command");
+
+        Self::new()
+            .name(Some(task))
+            .globs(globs)
+            .arguments(arguments)
+            .check()?
+            ._up()?
+            ._watch()?;
+
+        Ok(())
+    }
+
     pub fn up() -> Result<(), Error> {
         trace!("up command");
 
@@ -143,40 +260,154 @@ impl Command {
         Ok(())
     }
 
-    pub fn clone(name: Option<String>, all: bool) -> Result<(), Error> {
+    pub fn clone(
+        name: Option<String>,
+        all: bool,
+        clone_options: git::CloneOptions,
+        bootstrap: bool,
+    ) -> Result<(), Error> {
         trace!("clone command");
 
-        Self::new().name(name).all(all).check()?._up()?._clone()?;
+        Self::new()
+            .name(name)
+            .all(all)
+            .clone_options(clone_options)
+            .bootstrap(bootstrap)
+            .check()?
+            ._up()?
+            ._clone()?;
 
         info!("Clone completed");
 
         Ok(())
     }
 
-    pub fn review(pr: Option<String>, name: Option<String>) -> Result<(), Error> {
+    pub fn review(
+        pr: Option<String>,
+        name: Option<String>,
+        worktree: bool,
+        stash: bool,
+        skip_dirty: bool,
+    ) -> Result<(), Error> {
         trace!("review command");
 
-        Self::new().name(name).pr(pr).check()?._up()?._review()?;
+        Self::new()
+            .name(name)
+            .pr(pr)
+            .worktree(worktree)
+            .stash(stash)
+            .skip_dirty(skip_dirty)
+            .check()?
+            ._up()?
+            ._review()?;
 
         info!("Review completed");
 
         Ok(())
     }
 
-    pub fn gh() -> Result<(), Error> {
+    pub fn review_done(pr: Option<String>, delete: bool) -> Result<(), Error> {
+        trace!("review done command");
+
+        Self::new().pr(pr).delete(delete).check()?._review_done()?;
+
+        info!("Review cleanup completed");
+
+        Ok(())
+    }
+
+    pub fn branch(branch: String, repos: Vec<String>) -> Result<(), Error> {
+        trace!("branch command");
+
+        Self::new()
+            .branch_name(Some(branch))
+            .repos(repos)
+            .check()?
+            ._branch(git::branch)?;
+
+        Ok(())
+    }
+
+    pub fn checkout(branch: String, repos: Vec<String>) -> Result<(), Error> {
+        trace!("checkout command");
+
+        Self::new()
+            .branch_name(Some(branch))
+            .repos(repos)
+            .check()?
+            ._branch(git::checkout)?;
+
+        Ok(())
+    }
+
+    pub fn workspace_save(name: String) -> Result<(), Error> {
+        trace!("workspace save command");
+
+        Self::new().name(Some(name)).check()?._workspace_save()?;
+
+        Ok(())
+    }
+
+    pub fn workspace_restore(name: String) -> Result<(), Error> {
+        trace!("workspace restore command");
+
+        Self::new().name(Some(name)).check()?._workspace_restore()?;
+
+        Ok(())
+    }
+
+    pub fn gh(page: git::Page) -> Result<(), Error> {
         trace!("gh command");
 
-        git::open()?;
+        git::open(page)?;
 
         Ok(())
     }
 
-    pub fn run(name: Option<String>, arguments: Vec<String>) -> Result<(), Error> {
+    pub fn hooks_install(force: bool) -> Result<(), Error> {
+        trace!("hooks install command");
+
+        shell::Shell::check_setup()?;
+        Self::new()._each_repo(|repo| hooks::install(repo, force))?;
+
+        Ok(())
+    }
+
+    pub fn hooks_status() -> Result<(), Error> {
+        trace!("hooks status command");
+
+        Self::new()._each_repo(hooks::status)?;
+
+        Ok(())
+    }
+
+    pub fn status() -> Result<(), Error> {
+        trace!("status command");
+
+        Self::new()._status()?;
+
+        Ok(())
+    }
+
+    pub fn ticket(all: bool) -> Result<(), Error> {
+        trace!("ticket command");
+
+        if all {
+            Self::new()._tickets()?;
+        } else {
+            Self::new()._ticket()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn run(name: Option<String>, arguments: Vec<String>, no_docker: bool) -> Result<(), Error> {
         trace!("run command");
 
         Self::new()
             .name(name)
             .arguments(arguments)
+            .no_docker(no_docker)
             .check()?
             ._up()?
             ._run()?;
@@ -184,21 +415,86 @@ impl Command {
         Ok(())
     }
 
-    pub fn all(&mut self, all: bool) -> &mut Self {
+    pub const fn all(&mut self, all: bool) -> &mut Self {
         self.all = all;
         self
     }
 
-    pub fn docker_only(&mut self, docker_only: bool) -> &mut Self {
+    pub const fn docker_only(&mut self, docker_only: bool) -> &mut Self {
         self.docker_only = docker_only;
         self
     }
 
+    pub const fn no_docker(&mut self, no_docker: bool) -> &mut Self {
+        self.no_docker = no_docker;
+        self
+    }
+
+    pub const fn delete(&mut self, delete: bool) -> &mut Self {
+        self.delete = delete;
+        self
+    }
+
+    pub const fn worktree(&mut self, worktree: bool) -> &mut Self {
+        self.worktree = worktree;
+        self
+    }
+
+    pub const fn stash(&mut self, stash: bool) -> &mut Self {
+        self.stash = stash;
+        self
+    }
+
+    pub const fn skip_dirty(&mut self, skip_dirty: bool) -> &mut Self {
+        self.skip_dirty = skip_dirty;
+        self
+    }
+
+    pub const fn tmux(&mut self, tmux: bool) -> &mut Self {
+        self.tmux = tmux;
+        self
+    }
+
+    pub const fn open(&mut self, open: bool) -> &mut Self {
+        self.open = open;
+        self
+    }
+
+    pub const fn bootstrap(&mut self, bootstrap: bool) -> &mut Self {
+        self.bootstrap = bootstrap;
+        self
+    }
+
+    pub fn clone_options(&mut self, clone_options: git::CloneOptions) -> &mut Self {
+        self.clone_options = clone_options;
+        self
+    }
+
+    pub fn globs(&mut self, globs: Vec<String>) -> &mut Self {
+        self.globs = globs;
+        self
+    }
+
+    pub fn junit(&mut self, junit: Option<String>) -> &mut Self {
+        self.junit = junit;
+        self
+    }
+
     pub fn name(&mut self, name: Option<String>) -> &mut Self {
         self.name = name;
         self
     }
 
+    pub fn branch_name(&mut self, branch_name: Option<String>) -> &mut Self {
+        self.branch_name = branch_name;
+        self
+    }
+
+    pub fn repos(&mut self, repos: Vec<String>) -> &mut Self {
+        self.repos = repos;
+        self
+    }
+
     pub fn pr(&mut self, pr: Option<String>) -> &mut Self {
         self.pr = pr;
         self
@@ -216,6 +512,13 @@ impl Command {
     }
 
     pub fn _up(&self) -> Result<&Self, Error> {
+        if self.no_docker {
+            info!("Not starting the docker services");
+
+            return Ok(self);
+        }
+
+        ports::check()?;
         shell::docker_up()?;
 
         Ok(self)
@@ -242,14 +545,216 @@ impl Command {
     fn _start(&self) -> Result<&Self, Error> {
         if self.docker_only {
             info!("Starting only docker services");
-        } else if Path::new(".pndev/start").exists() {
+
+            return Ok(self);
+        }
+
+        let processes = procs::load(Path::new("."))?;
+
+        // the health check starts with the app, never for a command that isn't run
+        if Path::new(".pndev/start").exists() {
+            self._wait_for_health(Path::new("."))?;
             self._run_command("start")?;
-        } else if Path::new("pndev.toml").exists() {
+        } else if self._toml_task("start")?.is_some() {
+            self._wait_for_health(Path::new("."))?;
             self._run_pndev_toml_command("start")?;
-        } else if Path::new("ember-cli-build.js").exists() {
-            shell::ember_start()?;
+        } else if !processes.is_empty() {
+            self._wait_for_health(Path::new("."))?;
+            procs::supervise(&processes)?;
+        } else if let Some(detection) = detect::detect(Path::new(".")) {
+            match &detection.start {
+                Some(start) => {
+                    println!(
+                        "{} {} app detected{}",
+                        Green.paint("✓"),
+                        detection.name,
+                        detection
+                            .port
+                            .map_or_else(String::new, |port| format!(", starting on port {port}"))
+                    );
+                    self._wait_for_health(Path::new("."))?;
+                    shell::run(start)?;
+                }
+                None => bail!(
+                    "{} app found but there is no default start command, add a start task to pndev.toml",
+                    detection.name
+                ),
+            }
+        } else {
+            bail!("No app found, run pndev start --explain for details")
+        }
+
+        Ok(self)
+    }
+
+    /// Starts every app from its own repo, multiplexed or in tmux
+    fn _start_apps(&self) -> Result<&Self, Error> {
+        let repo_path = config::Config::new().repo_path();
+        let mut apps = vec![];
+
+        for app in self._repos() {
+            let dir = PathBuf::from(format!("{repo_path}/{app}"));
+
+            if !dir.exists() {
+                bail!("{} is not cloned, run pndev clone {}", app, app);
+            }
+
+            let processes = procs::app_processes(&app, &dir)?;
+            apps.push((app, processes));
+        }
+
+        if self.tmux {
+            if procs::tmux_available() {
+                procs::tmux(&apps)?;
+
+                return Ok(self);
+            }
+
+            println!(
+                "{} tmux not found, starting the apps here",
+                Yellow.paint("⚠")
+            );
+        }
+
+        // apps behind nginx share the default URL, it is polled once
+        let mut urls = vec![];
+        for (app, _) in &apps {
+            if let Some(url) = health::url(&PathBuf::from(format!("{repo_path}/{app}")))? {
+                if !urls.contains(&url) {
+                    urls.push(url);
+                }
+            }
+        }
+
+        let processes: Vec<procs::Process> = apps
+            .into_iter()
+            .flat_map(|(_, processes)| processes)
+            .collect();
+        if processes.is_empty() {
+            bail!("No processes to start");
+        }
+
+        for url in urls {
+            health::wait_for(url, self.open)?;
+        }
+
+        procs::supervise(&processes)?;
+
+        Ok(self)
+    }
+
+    /// Reports in the background when the app answers on its health URL
+    fn _wait_for_health(&self, dir: &Path) -> Result<&Self, Error> {
+        if let Some(url) = health::url(dir)? {
+            health::wait_for(url, self.open)?;
+        }
+
+        Ok(self)
+    }
+
+    /// Shows how `_start` picks the start command, without running anything
+    fn _explain_start(&self) -> Result<&Self, Error> {
+        println!("Start command for {}:", std::env::current_dir()?.display());
+
+        if Path::new(".pndev/start").exists() {
+            println!("{} .pndev/start script found", Green.paint("✓"));
+            return Ok(self);
+        }
+        println!("{} no .pndev/start script", Red.paint("✗"));
+
+        if let Some(task) = self._toml_task("start")? {
+            println!("{} pndev.toml start task: {}", Green.paint("✓"), task);
+            return Ok(self);
+        }
+        println!("{} no start task in pndev.toml", Red.paint("✗"));
+
+        let processes = procs::load(Path::new("."))?;
+        if !processes.is_empty() {
+            println!(
+                "{} processes from pndev.toml or Procfile:",
+                Green.paint("✓")
+            );
+            for process in processes {
+                println!("  {}: {}", process.name, process.cmd);
+            }
+            return Ok(self);
+        }
+        println!("{} no processes in pndev.toml or Procfile", Red.paint("✗"));
+
+        match detect::detect(Path::new(".")) {
+            Some(detection) => {
+                let mark = if detection.start.is_some() {
+                    Green.paint("✓")
+                } else {
+                    Yellow.paint("⚠")
+                };
+                println!("{} {} detected: {}", mark, detection.name, detection.reason);
+                println!(
+                    "  start: {}",
+                    detection
+                        .start
+                        .as_deref()
+                        .unwrap_or("none, add a start task to pndev.toml")
+                );
+                if let Some(port) = detection.port {
+                    println!("  port: {port}");
+                }
+            }
+            None => println!(
+                "{} no Ember, Rails, Ruby, Phoenix, Django, Python, Next.js or Node app detected",
+                Red.paint("✗")
+            ),
+        }
+
+        Ok(self)
+    }
+
+    /// `name` task of the pndev.toml in the current dir
+    fn _toml_task(&self, name: &str) -> Result<Option<String>, Error> {
+        if !Path::new("pndev.toml").exists() {
+            return Ok(None);
+        }
+
+        Ok(parse::task(&parse::config()?, name)?.map(|task| task.cmd))
+    }
+
+    /// Runs the test suite, `.pndev/test` and the pndev.toml test task win over detection
+    fn _test(&self) -> Result<&Self, Error> {
+        let detected = detect::detect(Path::new("."))
+            .and_then(|detection| Some((detection.name, detection.test?)));
+
+        let cmd = if Path::new(".pndev/test").exists() {
+            String::from("./.pndev/test")
+        } else if let Some(task) = self._toml_task("test")? {
+            task
+        } else if let Some((name, test)) = detected {
+            info!("{} app detected", name);
+            if name == "Ember" {
+                if let Some(path) = self.arguments.iter().find(|arg| Path::new(arg).exists()) {
+                    bail!(
+                        "ember test doesn't take paths ({}), filter the tests with pndev test -- --filter NAME",
+                        path
+                    )
+                }
+            }
+            test
         } else {
-            bail!("No Ruby or Ember app found")
+            bail!("No test command found, add a test task to pndev.toml")
+        };
+
+        let args = self._args_to_string();
+        info!("running tests: {} {}", cmd, args);
+
+        let (status, output) = shell::run_captured(&[cmd.as_str(), &args].join(" "))?;
+
+        let report = report::Report::parse(&output);
+        if let Some(path) = &self.junit {
+            report.write_junit(path)?;
+        }
+        report.print_summary();
+
+        if !status.success() {
+            bail!("Tests failed (exit code: {})", status.code().unwrap_or(1))
         }
 
         Ok(self)
@@ -274,6 +779,40 @@ impl Command {
         Ok(self)
     }
 
+    /// Like `_run`, restarting the task on file changes
+    fn _watch(&self) -> Result<&Self, Error> {
+        let name = match &self.name {
+            Some(name) => name,
+            None => bail!("Please specify a task to watch"),
+        };
+
+        let task = if Path::new(&["./.pndev", name].join("/")).exists() {
+            parse::Task {
+                cmd: ["./.pndev", name].join("/"),
+                ..parse::Task::default()
+            }
+        } else if Path::new("pndev.toml").exists() {
+            match parse::task(&parse::config()?, name)? {
+                Some(task) => task,
+                None => bail!("Command {} not found", name),
+            }
+        } else {
+            bail!("Command {} not found", name)
+        };
+
+        let globs = if self.globs.is_empty() {
+            &task.watch
+        } else {
+            &self.globs
+        };
+        let cmd = [task.cmd.as_str(), &self._args_to_string()].join(" ");
+
+        info!("watching {:?} to restart {}", globs, cmd);
+        watch::run(&cmd, globs, &task.ignore)?;
+
+        Ok(self)
+    }
+
     fn _down(&self) -> Result<&Self, Error> {
         shell::docker_down()?;
 
@@ -340,39 +879,384 @@ impl Command {
     }
 
     fn _clone(&self) -> Result<&Self, Error> {
-        if self.all {
-            for &app in REPOS {
-                println!("Cloning {app}");
-                git::clone(app)?;
-            }
+        let apps = if self.all {
+            REPOS.to_vec()
         } else {
             match &self.name {
-                Some(name) => {
-                    println!("Cloning {name}");
-                    git::clone(name)?;
-                }
+                Some(name) => vec![name.as_str()],
                 None => bail!("Please specify an app name or --all"),
             }
+        };
+
+        let mut failed_clones = vec![];
+        let mut failed = vec![];
+
+        for app in apps {
+            println!("Cloning {app}");
+
+            if let Err(err) = git::clone_with(app, &self.clone_options) {
+                println!("{} clone of {} failed\n{}", Red.paint("✗"), app, err);
+                failed_clones.push(app);
+                continue;
+            }
+
+            if self.bootstrap {
+                if let Err(err) = self._bootstrap(app) {
+                    println!("{} bootstrap of {} failed\n{}", Red.paint("✗"), app, err);
+                    failed.push(app);
+                }
+            }
+        }
+
+        match (failed_clones.is_empty(), failed.is_empty()) {
+            (true, true) => {}
+            (false, true) => bail!("Clone failed for {}", failed_clones.join(", ")),
+            (true, false) => bail!("Bootstrap failed for {}", failed.join(", ")),
+            (false, false) => bail!(
+                "Clone failed for {}, bootstrap failed for {}",
+                failed_clones.join(", "),
+                failed.join(", ")
+            ),
         }
 
         Ok(self)
     }
 
+    /// Runs the bootstrap task of a freshly cloned app, from its directory
+    fn _bootstrap(&self, app: &str) -> Result<(), Error> {
+        let path = format!("{}/{app}", config::Config::new().repo_path());
+
+        std::env::set_current_dir(path)?;
+
+        if Path::new(".pndev/bootstrap").exists() {
+            self._run_command("bootstrap")?;
+        } else if Path::new("pndev.toml").exists() && parse::config()?.get("bootstrap").is_some() {
+            self._run_pndev_toml_command("bootstrap")?;
+        } else {
+            println!("{} {} has no bootstrap task", Yellow.paint("⚠"), app);
+            return Ok(());
+        }
+
+        println!("{} bootstrapped {}", Green.paint("✓"), app);
+
+        Ok(())
+    }
+
     fn _review(&self) -> Result<&Self, Error> {
+        let mut entries = vec![];
+
+        if let Some(pr) = &self.pr {
+            git::check_review_target(pr)?;
+        }
+
         match &self.pr {
-            Some(pr) => match &self.name {
-                Some(name) => {
-                    info!("Pulling {}:{} for review", name, pr);
-                    git::review(name, pr)?;
+            Some(pr) if git::is_pull_request(pr) && self.name.is_none() => {
+                bail!("Pull request numbers are per repository, please specify one with --name")
+            }
+            Some(pr) => {
+                let apps = self
+                    .name
+                    .as_ref()
+                    .map_or_else(|| APPS.to_vec(), |name| vec![name.as_str()]);
+
+                // sort out dirty trees before touching any repo,
+                // worktrees leave the main checkout alone so they don't care
+                let mut state = review::State::load()?;
+                let mut to_review = vec![];
+                for app in apps {
+                    let stash = if !self.worktree && git::is_dirty(app)? {
+                        match self._dirty_action(app)? {
+                            // only one stash per repo is recorded for `--done` to pop
+                            DirtyAction::Stash if state.stash(pr, app).is_some() => bail!(
+                                "{} already has changes stashed by pndev review {}, commit or discard the new ones, or run pndev review --done {} first",
+                                app,
+                                pr,
+                                pr
+                            ),
+                            DirtyAction::Stash => true,
+                            DirtyAction::Skip => {
+                                println!(
+                                    "{} skipping {}, it has local changes",
+                                    Yellow.paint("⚠"),
+                                    app
+                                );
+                                continue;
+                            }
+                        }
+                    } else {
+                        false
+                    };
+
+                    to_review.push((app, stash));
                 }
-                None => {
-                    for &app in APPS {
-                        info!("Pulling {}:{} for review", app, pr);
-                        git::review(app, pr)?;
+
+                for (app, stash) in to_review {
+                    info!("Pulling {}:{} for review", app, pr);
+                    if self.worktree {
+                        entries.extend(git::review_worktree(app, pr)?);
+                    } else {
+                        entries.extend(git::review(app, pr, stash)?);
                     }
                 }
-            },
-            None => bail!("Please specify a Pull Request (branch name)"),
+
+                state.record(pr, entries);
+                state.save()?;
+            }
+            None => bail!("Please specify a Pull Request (branch name or #number)"),
+        }
+
+        Ok(self)
+    }
+
+    fn _dirty_action(&self, app: &str) -> Result<DirtyAction, Error> {
+        if self.stash {
+            return Ok(DirtyAction::Stash);
+        }
+
+        if self.skip_dirty {
+            return Ok(DirtyAction::Skip);
+        }
+
+        let choice = Select::new()
+            .with_prompt(format!("{app} has uncommitted changes"))
+            .items(&[
+                "stash them (pndev review --done pops them back)",
+                "skip this repo",
+                "abort",
+            ])
+            .default(0)
+            .interact()?;
+
+        match choice {
+            0 => Ok(DirtyAction::Stash),
+            1 => Ok(DirtyAction::Skip),
+            _ => bail!("User abort"),
+        }
+    }
+
+    fn _review_done(&self) -> Result<&Self, Error> {
+        let mut state = review::State::load()?;
+        let reviews = state.take(self.pr.as_deref());
+
+        if reviews.is_empty() {
+            match &self.pr {
+                Some(pr) => bail!("No review in progress for {}", pr),
+                None => bail!("No review in progress"),
+            }
+        }
+
+        for (pr, entries) in reviews {
+            info!("Finishing review of {}", pr);
+
+            // keep what could not be undone, e.g. on a dirty tree, so `--done` can be run again
+            let mut left = vec![];
+
+            for entry in entries {
+                match git::review_done(&entry, self.delete) {
+                    Ok(None) => {}
+                    Ok(Some(entry)) => left.push(entry),
+                    Err(err) => {
+                        println!("{} Error finishing {}\n{}", Red.paint("✗"), entry.repo, err);
+                        left.push(entry);
+                    }
+                }
+            }
+
+            if !left.is_empty() {
+                println!(
+                    "{} {} kept, fix the errors above and run `pndev review --done` again",
+                    Yellow.paint("⚠"),
+                    pr
+                );
+                state.record(&pr, left);
+            }
+        }
+
+        state.save()?;
+
+        Ok(self)
+    }
+
+    /// --repos with aliases expanded, or all the APP repos
+    fn _repos(&self) -> Vec<String> {
+        if self.repos.is_empty() {
+            return APPS.iter().map(|&app| app.to_owned()).collect();
+        }
+
+        self.repos
+            .iter()
+            .map(|repo| {
+                ALIASES
+                    .iter()
+                    .find(|(alias, _)| alias == repo)
+                    .map_or_else(|| repo.clone(), |(_, name)| (*name).to_owned())
+            })
+            .collect()
+    }
+
+    /// Runs a branch switching function on every repo, then reports the skipped ones
+    fn _branch(&self, switch: fn(&str, &str) -> Result<bool, Error>) -> Result<&Self, Error> {
+        let branch = match &self.branch_name {
+            Some(branch) => branch,
+            None => bail!("Please specify a branch name"),
+        };
+        let repo_path = config::Config::new().repo_path();
+        let mut skipped = vec![];
+
+        for repo in self._repos() {
+            if !Path::new(&format!("{repo_path}/{repo}")).exists() {
+                println!("{} {} is not cloned", Yellow.paint("⚠"), repo);
+                skipped.push(repo);
+                continue;
+            }
+
+            match switch(&repo, branch) {
+                Ok(true) => {}
+                Ok(false) => skipped.push(repo),
+                Err(err) => {
+                    println!("{} Error switching {}\n{}", Red.paint("✗"), repo, err);
+                    skipped.push(repo);
+                }
+            }
+        }
+
+        if !skipped.is_empty() {
+            println!("Skipped: {}", skipped.join(", "));
+        }
+
+        Ok(self)
+    }
+
+    fn _workspace_save(&self) -> Result<&Self, Error> {
+        match &self.name {
+            Some(name) => workspace::save(name, REPOS)?,
+            None => bail!("Please specify a workspace name"),
+        }
+
+        Ok(self)
+    }
+
+    fn _workspace_restore(&self) -> Result<&Self, Error> {
+        match &self.name {
+            Some(name) => workspace::restore(name)?,
+            None => bail!("Please specify a workspace name"),
+        }
+
+        Ok(self)
+    }
+
+    /// Runs `f` on every cloned repo, reporting the repos it fails on
+    fn _each_repo<F>(&self, f: F) -> Result<&Self, Error>
+    where
+        F: Fn(&str) -> Result<(), Error>,
+    {
+        let repo_path = config::Config::new().repo_path();
+        let mut failed = vec![];
+
+        for &repo in REPOS {
+            if !Path::new(&format!("{repo_path}/{repo}")).exists() {
+                continue;
+            }
+
+            if let Err(err) = f(repo) {
+                println!("{} {}: {}", Red.paint("✗"), repo, err);
+                failed.push(repo);
+            }
+        }
+
+        if !failed.is_empty() {
+            bail!("Failed for {}", failed.join(", "));
+        }
+
+        Ok(self)
+    }
+
+    fn _status(&self) -> Result<&Self, Error> {
+        let repo_path = config::Config::new().repo_path();
+
+        for &repo in REPOS {
+            if !Path::new(&format!("{repo_path}/{repo}")).exists() {
+                println!("{repo:<22} {}", Yellow.paint("not cloned"));
+                continue;
+            }
+
+            let status = match git::status(repo) {
+                Ok(status) => status,
+                Err(err) => {
+                    println!("{repo:<22} {}", Red.paint(format!("error: {err}")));
+                    continue;
+                }
+            };
+            let branch = status
+                .branch
+                .unwrap_or_else(|| format!("({})", &status.commit[..8]));
+            let ahead_behind = match status.ahead_behind {
+                Some((0, 0)) | None => String::new(),
+                Some((ahead, behind)) => format!(" ↑{ahead} ↓{behind}"),
+            };
+            let dirty = if status.dirty {
+                Red.paint(" uncommitted changes").to_string()
+            } else {
+                String::new()
+            };
+
+            println!("{repo:<22} {branch}{ahead_behind}{dirty}");
+        }
+
+        Ok(self)
+    }
+
+    fn _ticket(&self) -> Result<&Self, Error> {
+        let config = config::Config::new();
+        let regex = Regex::new(config.ticket_regex())?;
+        let branch = git::current_branch()?;
+
+        let ticket = match ticket::extract(&regex, &branch) {
+            Some(ticket) => ticket,
+            None => bail!("No ticket ID found in branch {}", branch),
+        };
+
+        match config.ticket_url() {
+            Some(template) => {
+                let url = ticket::url(template, &ticket);
+                info!("opening {}", url);
+                open::that(url)?;
+            }
+            None => bail!(
+                "Please set ticket_url in ~/.pndev_config.toml, i.e. ticket_url = 'https://precisionnutrition.atlassian.net/browse/{{ticket}}'"
+            ),
+        }
+
+        Ok(self)
+    }
+
+    fn _tickets(&self) -> Result<&Self, Error> {
+        let config = config::Config::new();
+        let regex = Regex::new(config.ticket_regex())?;
+        let repo_path = config.repo_path();
+
+        for &repo in REPOS {
+            if !Path::new(&format!("{repo_path}/{repo}")).exists() {
+                continue;
+            }
+
+            let branch = match git::repo_branch(repo) {
+                Ok(branch) => branch,
+                Err(err) => {
+                    println!("{:<12} {repo} {}", "-", Red.paint(format!("error: {err}")));
+                    continue;
+                }
+            };
+            match ticket::extract(&regex, &branch) {
+                Some(ticket) => {
+                    let url = config
+                        .ticket_url()
+                        .map(|template| format!(" {}", ticket::url(template, &ticket)))
+                        .unwrap_or_default();
+                    println!("{ticket:<12} {repo} ({branch}){url}");
+                }
+                None => println!("{:<12} {repo} ({branch})", "-"),
+            }
         }
 
         Ok(self)
@@ -381,16 +1265,13 @@ impl Command {
     fn _run_pndev_toml_command(&self, name: &str) -> Result<(), Error> {
         if Path::new("pndev.toml").exists() {
             let command = parse::config()?;
-            match command.get(name) {
-                Some(cmd) => match cmd.as_str() {
-                    Some(cmd) => {
-                        info!("executing {} command: {}", name, cmd);
-                        let args = self._args_to_string();
+            match parse::task(&command, name)? {
+                Some(task) => {
+                    info!("executing {} command: {}", name, task.cmd);
+                    let args = self._args_to_string();
 
-                        shell::nix(&[cmd, &args].join(" "))?;
-                    }
-                    None => bail!("Invalid {} command", name),
-                },
+                    shell::nix(&[task.cmd.as_str(), &args].join(" "))?;
+                }
                 None => bail!("Command {} not found", name),
             }
         } else {
@@ -1,11 +1,35 @@
 use dirs::home_dir;
 use log::info;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     install_path: String,
     docker_compose_path: Option<String>,
+    review_path: Option<String>,
+    ticket_url: Option<String>,
+    ticket_regex: Option<String>,
+    git_host: Option<String>,
+    git_org: Option<String>,
+    https_url_template: Option<String>,
+    clone_depth: Option<u32>,
+    clone_filter: Option<String>,
+    clone_cache: Option<bool>,
+    clone_https: Option<bool>,
+    /// named sets of apps for `pndev start --stack`, tables go last in toml
+    stacks: Option<BTreeMap<String, Vec<String>>>,
+    /// host ports of docker services, e.g. `postgres = 5433` in `[ports]`
+    ports: Option<BTreeMap<String, PortOverride>>,
+}
+
+/// The new host port of a service publishing one port, or of each of its
+/// container ports, e.g. `mailcatcher = { 1025 = 2025, 1080 = 2080 }`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PortOverride {
+    Port(u16),
+    Ports(BTreeMap<String, u16>),
 }
 
 /// `MyConfig` implements `Default`
@@ -14,6 +38,18 @@ impl ::std::default::Default for Config {
         Self {
             install_path: String::from("DEV/PN"),
             docker_compose_path: None,
+            review_path: None,
+            ticket_url: None,
+            ticket_regex: None,
+            git_host: None,
+            git_org: None,
+            https_url_template: None,
+            clone_depth: None,
+            clone_filter: None,
+            clone_cache: None,
+            clone_https: None,
+            stacks: None,
+            ports: None,
         }
     }
 }
@@ -29,6 +65,16 @@ impl Config {
         cfg
     }
 
+    /// where `new` loads the config from, and creates it with defaults if missing
+    pub fn file_path() -> String {
+        format!("{}/{}.toml", Self::home_path_str(), CONFIG_FILE_NAME)
+    }
+
+    /// where pndev keeps its own logs, included in `pndev doctor --bundle`
+    pub fn log_path() -> String {
+        format!("{}/.pndev.log", Self::home_path_str())
+    }
+
     pub fn home_path_str() -> String {
         let home_path = home_dir().unwrap();
         home_path.into_os_string().into_string().unwrap()
@@ -38,6 +84,91 @@ impl Config {
         format!("{}/{}", Self::home_path_str(), self.install_path)
     }
 
+    /// where `pndev review --worktree` creates its worktrees
+    pub fn review_path(&self) -> String {
+        self.review_path.as_ref().map_or_else(
+            || format!("{}/review", self.repo_path()),
+            |path| format!("{}/{}", Self::home_path_str(), path),
+        )
+    }
+
+    /// ticket tracker url with a `{ticket}` placeholder
+    pub fn ticket_url(&self) -> Option<&str> {
+        self.ticket_url.as_deref()
+    }
+
+    /// regex matching the ticket ID in branch names
+    pub fn ticket_regex(&self) -> &str {
+        self.ticket_regex
+            .as_deref()
+            .unwrap_or(r"[A-Z][A-Z0-9]+-[0-9]+")
+    }
+
+    fn git_host(&self) -> &str {
+        self.git_host.as_deref().unwrap_or("github.com")
+    }
+
+    fn git_org(&self) -> &str {
+        self.git_org.as_deref().unwrap_or("PrecisionNutrition")
+    }
+
+    pub fn ssh_url(&self, name: &str) -> String {
+        format!("git@{}:{}/{name}.git", self.git_host(), self.git_org())
+    }
+
+    /// https url built from a template with `{host}`, `{org}` and `{name}` placeholders
+    #[allow(clippy::literal_string_with_formatting_args)]
+    pub fn https_url(&self, name: &str) -> String {
+        self.https_url_template
+            .as_deref()
+            .unwrap_or("https://{host}/{org}/{name}.git")
+            .replace("{host}", self.git_host())
+            .replace("{org}", self.git_org())
+            .replace("{name}", name)
+    }
+
+    pub const fn clone_depth(&self) -> Option<u32> {
+        self.clone_depth
+    }
+
+    pub fn clone_filter(&self) -> Option<&str> {
+        self.clone_filter.as_deref()
+    }
+
+    pub fn clone_cache(&self) -> bool {
+        self.clone_cache.unwrap_or(false)
+    }
+
+    pub fn clone_https(&self) -> bool {
+        self.clone_https.unwrap_or(false)
+    }
+
+    /// the catalog shipped with the pndev repo
+    pub fn catalog_path(&self) -> String {
+        format!("{}/pndev/catalog", self.repo_path())
+    }
+
+    /// apps of a stack, e.g. `full = ["es", "fitpro"]` in `[stacks]`
+    pub fn stack(&self, name: &str) -> Option<&[String]> {
+        self.stacks
+            .as_ref()
+            .and_then(|stacks| stacks.get(name))
+            .map(Vec::as_slice)
+    }
+
+    /// host port overrides from `[ports]`
+    pub fn ports(&self) -> BTreeMap<String, PortOverride> {
+        self.ports.clone().unwrap_or_default()
+    }
+
+    /// compose file generated from `[ports]`, passed after `docker_compose_path`
+    pub fn docker_compose_override_path() -> String {
+        format!(
+            "{}/.pndev_docker-compose.override.yml",
+            Self::home_path_str()
+        )
+    }
+
     pub fn docker_compose_path(&self) -> String {
         self.docker_compose_path.as_ref().map_or_else(
             || {
@@ -0,0 +1,257 @@
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// What a detector found in a project directory
+#[derive(Debug)]
+pub struct Detection {
+    pub name: &'static str,
+    /// why the detector matched, shown by `pndev start --explain`
+    pub reason: String,
+    /// commands run inside nix-shell
+    pub start: Option<String>,
+    pub test: Option<String>,
+    /// port the app listens on when started with `start`
+    pub port: Option<u16>,
+}
+
+type Detector = fn(&Path) -> Option<Detection>;
+
+/// Checked in order, the first match wins: ember apps also have a package.json
+/// and Rails apps a Gemfile, so the more specific detectors come first
+const DETECTORS: &[Detector] = &[ember, rails, ruby, phoenix, django, python, next, node];
+
+/// Detects the app in `dir`
+pub fn detect(dir: &Path) -> Option<Detection> {
+    DETECTORS.iter().find_map(|detector| detector(dir))
+}
+
+fn ember(dir: &Path) -> Option<Detection> {
+    if !dir.join("ember-cli-build.js").exists() {
+        return None;
+    }
+
+    Some(Detection {
+        name: "Ember",
+        reason: String::from("ember-cli-build.js found"),
+        start: Some(String::from("yarn && yarn exec ember server")),
+        test: Some(String::from("yarn && yarn exec ember test")),
+        port: Some(4200),
+    })
+}
+
+fn rails(dir: &Path) -> Option<Detection> {
+    if !dir.join("Gemfile").exists() || !dir.join("config/application.rb").exists() {
+        return None;
+    }
+
+    Some(Detection {
+        name: "Rails",
+        reason: String::from("Gemfile and config/application.rb found"),
+        start: Some(String::from("bundle exec rails server")),
+        test: Some(rspec_or(dir, "bundle exec rails test")),
+        port: Some(3000),
+    })
+}
+
+fn ruby(dir: &Path) -> Option<Detection> {
+    if !dir.join("Gemfile").exists() {
+        return None;
+    }
+
+    Some(Detection {
+        name: "Ruby",
+        reason: String::from("Gemfile found, but no config/application.rb"),
+        start: None,
+        test: Some(rspec_or(dir, "bundle exec rake test")),
+        port: None,
+    })
+}
+
+fn rspec_or(dir: &Path, default: &str) -> String {
+    if dir.join("spec").exists() {
+        String::from("bundle exec rspec")
+    } else {
+        default.to_owned()
+    }
+}
+
+fn phoenix(dir: &Path) -> Option<Detection> {
+    let mix = fs::read_to_string(dir.join("mix.exs")).ok()?;
+
+    if !mix.contains(":phoenix,") {
+        return None;
+    }
+
+    Some(Detection {
+        name: "Phoenix",
+        reason: String::from("mix.exs depends on phoenix"),
+        start: Some(String::from("mix deps.get && mix phx.server")),
+        test: Some(String::from("mix test")),
+        port: Some(4000),
+    })
+}
+
+fn django(dir: &Path) -> Option<Detection> {
+    if !dir.join("manage.py").exists() {
+        return None;
+    }
+
+    Some(Detection {
+        name: "Django",
+        reason: String::from("manage.py found"),
+        start: Some(String::from("python manage.py runserver")),
+        test: Some(String::from("python manage.py test")),
+        port: Some(8000),
+    })
+}
+
+fn python(dir: &Path) -> Option<Detection> {
+    let manifest = ["pyproject.toml", "requirements.txt", "setup.py"]
+        .iter()
+        .find(|file| dir.join(file).exists())?;
+    let main = ["main.py", "app.py"]
+        .iter()
+        .find(|file| dir.join(file).exists());
+
+    Some(Detection {
+        name: "Python",
+        reason: main.map_or_else(
+            || format!("{manifest} found"),
+            |main| format!("{manifest} and {main} found"),
+        ),
+        start: main.map(|main| format!("python {main}")),
+        test: Some(String::from("python -m pytest")),
+        port: None,
+    })
+}
+
+fn package_json(dir: &Path) -> Option<Value> {
+    let content = fs::read_to_string(dir.join("package.json")).ok()?;
+
+    serde_json::from_str(&content).ok()
+}
+
+fn has_dependency(package: &Value, name: &str) -> bool {
+    ["dependencies", "devDependencies"]
+        .iter()
+        .any(|deps| package[deps].get(name).is_some())
+}
+
+/// First script of `names` defined in package.json
+fn script<'a>(package: &Value, names: &[&'a str]) -> Option<&'a str> {
+    names
+        .iter()
+        .copied()
+        .find(|name| package["scripts"].get(name).is_some())
+}
+
+fn next(dir: &Path) -> Option<Detection> {
+    let package = package_json(dir)?;
+
+    if !has_dependency(&package, "next") {
+        return None;
+    }
+
+    Some(Detection {
+        name: "Next.js",
+        reason: String::from("package.json depends on next"),
+        start: Some(script(&package, &["dev"]).map_or_else(
+            || String::from("yarn && yarn exec next dev"),
+            |script| format!("yarn && yarn {script}"),
+        )),
+        test: script(&package, &["test"]).map(|script| format!("yarn && yarn {script}")),
+        port: Some(3000),
+    })
+}
+
+fn node(dir: &Path) -> Option<Detection> {
+    let package = package_json(dir)?;
+    let start = script(&package, &["dev", "start"]);
+
+    Some(Detection {
+        name: "Node",
+        reason: start.map_or_else(
+            || String::from("package.json found, without a dev or start script"),
+            |script| format!("package.json has a {script} script"),
+        ),
+        start: start.map(|script| format!("yarn && yarn {script}")),
+        test: script(&package, &["test"]).map(|script| format!("yarn && yarn {script}")),
+        port: start.map(|_| 3000),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file and its content
+    type Marker = (&'static str, &'static str);
+
+    #[test]
+    fn picks_package_json_scripts() {
+        let package: Value = serde_json::from_str(
+            r#"{"scripts": {"start": "node server.js", "test": "jest"}, "devDependencies": {"next": "13"}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(script(&package, &["dev", "start"]), Some("start"));
+        assert_eq!(script(&package, &["lint"]), None);
+        assert!(has_dependency(&package, "next"));
+        assert!(!has_dependency(&package, "react"));
+    }
+
+    #[test]
+    fn detects_the_most_specific_app() {
+        let next = r#"{"dependencies": {"next": "13"}, "scripts": {"dev": "next dev"}}"#;
+        let cases: &[(&[Marker], Option<&str>)] = &[
+            (&[], None),
+            (
+                &[("ember-cli-build.js", ""), ("package.json", "{}")],
+                Some("Ember"),
+            ),
+            (
+                &[
+                    ("Gemfile", ""),
+                    ("config/application.rb", ""),
+                    ("package.json", next),
+                ],
+                Some("Rails"),
+            ),
+            (&[("Gemfile", ""), ("package.json", "{}")], Some("Ruby")),
+            (
+                &[
+                    ("mix.exs", "{:phoenix, \"~> 1.7\"},"),
+                    ("package.json", "{}"),
+                ],
+                Some("Phoenix"),
+            ),
+            (&[("mix.exs", "{:plug, \"~> 1.0\"},")], None),
+            (
+                &[("manage.py", ""), ("requirements.txt", "")],
+                Some("Django"),
+            ),
+            (
+                &[("requirements.txt", ""), ("package.json", "{}")],
+                Some("Python"),
+            ),
+            (&[("package.json", next)], Some("Next.js")),
+            (&[("package.json", "{}")], Some("Node")),
+        ];
+
+        for (files, expected) in cases {
+            let dir = tempfile::tempdir().unwrap();
+            for (file, content) in *files {
+                let path = dir.path().join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, content).unwrap();
+            }
+
+            assert_eq!(
+                detect(dir.path()).map(|detection| detection.name),
+                *expected,
+                "{files:?}"
+            );
+        }
+    }
+}
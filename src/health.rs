@@ -0,0 +1,169 @@
+use crate::parse;
+use ansi_term::Colour::{Green, Yellow};
+use failure::{bail, Error};
+use log::trace;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Through nginx, which answers 502 until the apps behind it are up
+const DEFAULT_HEALTH_URL: &str = "http://es-dev.precisionnutrition.com/";
+
+/// First boots install gems and node modules
+const READY_TIMEOUT: Duration = Duration::from_secs(600);
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The parts of a plain http URL needed to probe it
+#[derive(Debug, PartialEq, Eq)]
+struct Target {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+fn parse_url(url: &str) -> Result<Target, Error> {
+    let rest = match url.strip_prefix("http://") {
+        Some(rest) => rest,
+        None => bail!("Only http:// health URLs are supported, got {}", url),
+    };
+
+    let (authority, path) = rest
+        .find('/')
+        .map_or((rest, "/"), |i| (&rest[..i], &rest[i..]));
+
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse()?),
+        None => (authority, 80),
+    };
+
+    Ok(Target {
+        host: host.to_owned(),
+        port,
+        path: path.to_owned(),
+    })
+}
+
+/// `health_url` from the pndev.toml in `dir`, `health_url = false` disables the check
+pub fn url(dir: &Path) -> Result<Option<String>, Error> {
+    if !dir.join("pndev.toml").exists() {
+        return Ok(Some(DEFAULT_HEALTH_URL.to_owned()));
+    }
+
+    match parse::config_at(dir)?.get("health_url") {
+        Some(toml::Value::String(url)) => Ok(Some(url.to_owned())),
+        Some(toml::Value::Boolean(false)) => Ok(None),
+        Some(_) => bail!("Invalid health_url in pndev.toml"),
+        None => Ok(Some(DEFAULT_HEALTH_URL.to_owned())),
+    }
+}
+
+/// HTTP status code of a GET on the target
+fn probe(target: &Target) -> Result<u16, Error> {
+    let addr = match (target.host.as_str(), target.port)
+        .to_socket_addrs()?
+        .next()
+    {
+        Some(addr) => addr,
+        None => bail!("{} does not resolve", target.host),
+    };
+
+    let mut stream = TcpStream::connect_timeout(&addr, Duration::from_secs(2))?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        target.path, target.host
+    )?;
+
+    let mut head = [0; 64];
+    let read = stream.read(&mut head)?;
+
+    status_code(&String::from_utf8_lossy(&head[..read]))
+}
+
+/// Code from a `HTTP/1.1 200 OK` status line
+fn status_code(response: &str) -> Result<u16, Error> {
+    match response.split_whitespace().nth(1) {
+        Some(code) if response.starts_with("HTTP/") => Ok(code.parse()?),
+        _ => bail!("Not an HTTP response"),
+    }
+}
+
+/// Polls `url` in the background until it answers without a server error,
+/// then prints how long it took and optionally opens it in the browser
+pub fn wait_for(url: String, open: bool) -> Result<(), Error> {
+    let target = parse_url(&url)?;
+
+    thread::spawn(move || {
+        let started = Instant::now();
+
+        while started.elapsed() < READY_TIMEOUT {
+            match probe(&target) {
+                Ok(code) if code < 500 => {
+                    println!(
+                        "{} {} ready in {}s",
+                        Green.paint("✓"),
+                        url,
+                        started.elapsed().as_secs()
+                    );
+
+                    if open {
+                        if let Err(error) = open::that(&url) {
+                            println!("{} could not open {}: {}", Yellow.paint("⚠"), url, error);
+                        }
+                    }
+
+                    return;
+                }
+                result => trace!("{} not ready: {:?}", url, result),
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        println!(
+            "{} {} not ready after {} minutes",
+            Yellow.paint("⚠"),
+            url,
+            READY_TIMEOUT.as_secs() / 60
+        );
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_health_urls() {
+        assert_eq!(
+            parse_url("http://es-dev.precisionnutrition.com/").unwrap(),
+            Target {
+                host: "es-dev.precisionnutrition.com".to_owned(),
+                port: 80,
+                path: "/".to_owned(),
+            }
+        );
+        assert_eq!(
+            parse_url("http://localhost:4200").unwrap(),
+            Target {
+                host: "localhost".to_owned(),
+                port: 4200,
+                path: "/".to_owned(),
+            }
+        );
+        assert!(parse_url("https://example.com").is_err());
+    }
+
+    #[test]
+    fn parses_status_lines() {
+        assert_eq!(status_code("HTTP/1.1 502 Bad Gateway\r\n").unwrap(), 502);
+        assert!(status_code("SSH-2.0-OpenSSH").is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use crate::config;
+use crate::git;
+use crate::parse;
+use ansi_term::Colour::{Green, Red, Yellow};
+use failure::{bail, Error};
+use log::trace;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use toml::Value;
+
+/// Marks hooks installed by pndev, other hooks are never overwritten without --force
+const MARKER: &str = "# managed by pndev";
+
+/// A hook as pndev would install it in a repo
+#[derive(Debug)]
+struct Hook {
+    name: String,
+    content: String,
+}
+
+/// State of an installed hook compared to the expected one
+#[derive(Debug, PartialEq, Eq)]
+enum HookStatus {
+    UpToDate,
+    Outdated,
+    Missing,
+    /// a hook pndev did not install
+    Unmanaged,
+}
+
+/// Hooks from the catalog, overridden by the repo's pndev.toml:
+///
+/// ```toml
+/// [hooks]
+/// lint = "lint"                         # task run by the pre-push hook
+///
+/// [hooks.scripts]
+/// commit-msg = ".githooks/commit-msg"   # replaces the catalog hook
+/// pre-push = false                      # do not install this hook
+/// ```
+fn expected_hooks(repo_dir: &Path) -> Result<Vec<Hook>, Error> {
+    let cfg = config::Config::new();
+    let catalog = format!("{}/hooks", cfg.catalog_path());
+
+    if !Path::new(&catalog).exists() {
+        bail!("{} not found, run pndev update", catalog);
+    }
+
+    let settings = if repo_dir.join("pndev.toml").exists() {
+        match parse::config_at(repo_dir) {
+            Ok(toml) => toml.get("hooks").cloned(),
+            Err(err) => bail!("Invalid pndev.toml, its [hooks] can't be read: {}", err),
+        }
+    } else {
+        None
+    };
+    let lint_task = settings
+        .as_ref()
+        .and_then(|hooks| hooks.get("lint"))
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let scripts = settings.as_ref().and_then(|hooks| hooks.get("scripts"));
+
+    let mut hooks = vec![];
+
+    for entry in fs::read_dir(&catalog)? {
+        let path = entry?.path();
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name.to_owned(),
+            None => continue,
+        };
+
+        let content = match scripts.and_then(|scripts| scripts.get(&name)) {
+            Some(Value::Boolean(false)) => continue,
+            Some(Value::String(script)) => fs::read_to_string(repo_dir.join(script))?,
+            Some(_) => bail!("Invalid hooks.scripts.{} in pndev.toml", name),
+            None => fs::read_to_string(&path)?,
+        };
+
+        let content = content
+            .replace("{{ticket_regex}}", cfg.ticket_regex())
+            .replace("{{lint_task}}", lint_task);
+
+        hooks.push(Hook {
+            name,
+            content: with_marker(&content),
+        });
+    }
+
+    hooks.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(hooks)
+}
+
+/// Adds the marker right after the shebang of scripts that don't have it
+fn with_marker(content: &str) -> String {
+    if content.contains(MARKER) {
+        return content.to_owned();
+    }
+
+    match content.split_once('\n') {
+        Some((shebang, rest)) if shebang.starts_with("#!") => {
+            format!("{shebang}\n{MARKER}\n{rest}")
+        }
+        _ => format!("{MARKER}\n{content}"),
+    }
+}
+
+fn hook_status(hooks_dir: &Path, hook: &Hook) -> HookStatus {
+    match fs::read_to_string(hooks_dir.join(&hook.name)) {
+        Ok(installed) if installed == hook.content => HookStatus::UpToDate,
+        Ok(installed) if installed.contains(MARKER) => HookStatus::Outdated,
+        Ok(_) => HookStatus::Unmanaged,
+        Err(_) => HookStatus::Missing,
+    }
+}
+
+/// Installs the managed hooks in a workspace repo
+pub fn install(name: &str, force: bool) -> Result<(), Error> {
+    let repo_dir = git::repo_dir(name)?;
+    let hooks_dir = git::hooks_dir(name)?;
+
+    fs::create_dir_all(&hooks_dir)?;
+
+    for hook in expected_hooks(&repo_dir)? {
+        let status = hook_status(&hooks_dir, &hook);
+
+        if status == HookStatus::UpToDate {
+            println!("{} {}: {} up to date", Green.paint("✓"), name, hook.name);
+            continue;
+        }
+
+        if status == HookStatus::Unmanaged && !force {
+            println!(
+                "{} {}: {} exists and is not managed by pndev, use --force to replace it",
+                Yellow.paint("⚠"),
+                name,
+                hook.name
+            );
+            continue;
+        }
+
+        let path = hooks_dir.join(&hook.name);
+        trace!("installing hook {:?}", path);
+        fs::write(&path, &hook.content)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+
+        println!("{} {}: {} installed", Green.paint("✓"), name, hook.name);
+    }
+
+    Ok(())
+}
+
+/// Prints which managed hooks of a workspace repo are missing or outdated
+pub fn status(name: &str) -> Result<(), Error> {
+    let repo_dir = git::repo_dir(name)?;
+    let hooks_dir = git::hooks_dir(name)?;
+
+    for hook in expected_hooks(&repo_dir)? {
+        match hook_status(&hooks_dir, &hook) {
+            HookStatus::UpToDate => {
+                println!("{} {}: {} up to date", Green.paint("✓"), name, hook.name);
+            }
+            HookStatus::Outdated => {
+                println!("{} {}: {} outdated", Red.paint("✗"), name, hook.name);
+            }
+            HookStatus::Missing => {
+                println!("{} {}: {} not installed", Red.paint("✗"), name, hook.name);
+            }
+            HookStatus::Unmanaged => println!(
+                "{} {}: {} is not managed by pndev",
+                Yellow.paint("⚠"),
+                name,
+                hook.name
+            ),
+        }
+    }
+
+    Ok(())
+}
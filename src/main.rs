@@ -35,10 +35,47 @@ mod update;
 /// Configuration
 mod config;
 
+/// Review state
+mod review;
+
+/// Workspace snapshots
+mod workspace;
+
+/// Ticket tracker
+mod ticket;
+
+/// Git hooks
+mod hooks;
+
+/// Project type detection
+mod detect;
+
+/// Test results
+mod report;
+
+/// Process supervisor
+mod procs;
+
+/// App readiness
+mod health;
+
+/// File watching
+mod watch;
+
+/// Port conflicts
+mod ports;
+
+/// Support bundles
+mod bundle;
+
 /// Utils
 mod opt_log;
 mod parse;
 
+/// Test fixtures
+#[cfg(test)]
+mod testing;
+
 #[derive(Debug)]
 pub enum ResetType {
     Docker,
@@ -87,7 +124,20 @@ impl std::str::FromStr for ResetType {
 enum CliCommand {
     #[structopt(name = "doctor")]
     /// diagnose system setup for pndev
-    Doctor,
+    Doctor {
+        #[structopt(long = "fix")]
+        /// offer to fix the problems pndev can fix, after confirmation
+        fix: bool,
+
+        #[structopt(long = "json", conflicts_with_all = &["fix", "bundle"])]
+        /// print the result of each check as JSON
+        json: bool,
+
+        #[structopt(long = "bundle", conflicts_with = "fix")]
+        /// write a tarball with the doctor results, config, docker state,
+        /// recent logs and OS info for support, with secrets redacted
+        bundle: bool,
+    },
 
     #[structopt(name = "clone")]
     /// clone one or all the pn apps into ~/DEV/PN
@@ -99,20 +149,100 @@ enum CliCommand {
         #[structopt(name = "name")]
         /// name of the repository
         name: Option<String>,
+
+        #[structopt(long = "depth")]
+        /// shallow clone with history truncated to DEPTH commits
+        depth: Option<u32>,
+
+        #[structopt(long = "filter")]
+        /// partial clone filter, i.e. blob:none
+        filter: Option<String>,
+
+        #[structopt(long = "cache")]
+        /// clone through a local mirror cache in the install path, faster re-clones
+        cache: bool,
+
+        #[structopt(long = "no-cache", conflicts_with = "cache")]
+        /// clone without the mirror cache, even when clone_cache is set in the config
+        no_cache: bool,
+
+        #[structopt(long = "https")]
+        /// clone over https instead of ssh
+        https: bool,
+
+        #[structopt(long = "no-https", conflicts_with = "https")]
+        /// try ssh first, even when clone_https is set in the config
+        no_https: bool,
+
+        #[structopt(short = "b", long = "bootstrap")]
+        /// run the bootstrap task of each cloned repo (.pndev/bootstrap or pndev.toml)
+        bootstrap: bool,
     },
 
     #[structopt(name = "review")]
     /// checkout a PR in one or all APP repos (not including addons)
     Review {
         #[structopt(name = "pr")]
-        /// branch name (JIRA ticket ID)
+        /// branch name (JIRA ticket ID) or pull request number ('#1234', requires --name)
         pr: Option<String>,
 
         #[structopt(long = "name")]
         /// name of the repository
         name: Option<String>,
+
+        #[structopt(long = "done")]
+        /// go back to the branches checked out before reviewing PR (or every review)
+        done: bool,
+
+        #[structopt(long = "delete", requires = "done")]
+        /// with --done, also delete the local review branches
+        delete: bool,
+
+        #[structopt(long = "worktree", conflicts_with_all = &["stash", "skip-dirty", "done"])]
+        /// check out the PR in a separate worktree instead of switching branches
+        worktree: bool,
+
+        #[structopt(long = "stash", conflicts_with_all = &["skip-dirty", "done"])]
+        /// stash uncommitted changes without asking, --done pops them back
+        stash: bool,
+
+        #[structopt(long = "skip-dirty", conflicts_with = "done")]
+        /// leave repos with uncommitted changes alone without asking
+        skip_dirty: bool,
+    },
+
+    #[structopt(name = "branch")]
+    /// create (or switch to) the same branch in one or all APP repos, starting from their default branch
+    Branch {
+        #[structopt(name = "branch")]
+        /// branch name (JIRA ticket ID)
+        branch: String,
+
+        #[structopt(long = "repos", use_delimiter = true)]
+        /// comma separated list of repositories (es, fitpro..), defaults to all APP repos
+        repos: Vec<String>,
+    },
+
+    #[structopt(name = "checkout")]
+    /// switch every APP repo that has the branch locally or remotely
+    Checkout {
+        #[structopt(name = "branch")]
+        /// branch name (JIRA ticket ID)
+        branch: String,
+
+        #[structopt(long = "repos", use_delimiter = true)]
+        /// comma separated list of repositories (es, fitpro..), defaults to all APP repos
+        repos: Vec<String>,
     },
 
+    #[structopt(name = "workspace")]
+    /// save and restore the revisions checked out in every repo
+    Workspace(WorkspaceCommand),
+
+    #[structopt(name = "hooks")]
+    /// manage the pndev git hooks of every repo
+    Hooks(HooksCommand),
+
     #[structopt(name = "prepare")]
     /// prepares the db for eternal-sledgehammer
     Prepare {
@@ -127,6 +257,60 @@ enum CliCommand {
         #[structopt(short = "d", long = "only-docker")]
         /// do not attempt to start also rails or ember apps
         docker: bool,
+
+        #[structopt(long = "explain", conflicts_with = "docker")]
+        /// show how the app type and start command are detected, without starting anything
+        explain: bool,
+
+        #[structopt(
+            long = "apps",
+            use_delimiter = true,
+            group = "targets",
+            conflicts_with_all = &["docker", "explain"]
+        )]
+        /// comma separated list of apps (es, fitpro..) started together from their repos
+        apps: Vec<String>,
+
+        #[structopt(
+            long = "stack",
+            group = "targets",
+            conflicts_with_all = &["docker", "explain"]
+        )]
+        /// start the apps of a stack defined in ~/.pndev_config.toml
+        stack: Option<String>,
+
+        #[structopt(long = "tmux", requires = "targets")]
+        /// with --apps or --stack, start them in a tmux session with a window per app
+        tmux: bool,
+
+        #[structopt(long = "open", conflicts_with_all = &["docker", "explain", "tmux"])]
+        /// open the health URL in the browser once the app is ready
+        open: bool,
+    },
+
+    #[structopt(name = "test")]
+    /// run the project tests in nix-shell, rspec or ember test are detected
+    Test {
+        /// spec files or other arguments passed to the test runner
+        paths: Vec<String>,
+
+        #[structopt(long = "junit", name = "FILE")]
+        /// write a JUnit XML summary to FILE
+        junit: Option<String>,
+    },
+
+    #[structopt(name = "watch")]
+    /// run a task in nix-shell and restart it when files change
+    Watch {
+        /// .pndev script or pndev.toml task
+        task: String,
+
+        #[structopt(short = "g", long = "glob", number_of_values = 1)]
+        /// files to watch, overrides the task's watch globs, defaults to every file
+        globs: Vec<String>,
+
+        /// arguments passed to the task
+        arguments: Vec<String>,
     },
 
     #[structopt(name = "up")]
@@ -173,13 +357,73 @@ enum CliCommand {
 
     #[structopt(name = "gh")]
     /// opens the corresponding repo on github if available
-    Gh,
+    Gh {
+        #[structopt(name = "file")]
+        /// open FILE[:LINE] at the current commit
+        file: Option<String>,
+
+        #[structopt(long = "branch", conflicts_with_all = &["file", "pr", "actions"])]
+        /// open the current branch
+        branch: bool,
+
+        #[structopt(long = "pr", conflicts_with_all = &["file", "actions"])]
+        /// open the compare page to create a pull request for the current branch
+        pr: bool,
+
+        #[structopt(long = "actions", conflicts_with = "file")]
+        /// open github actions
+        actions: bool,
+    },
+
+    #[structopt(name = "status")]
+    /// branch, upstream distance (as of the last fetch) and local changes of every repo
+    Status,
+
+    #[structopt(name = "ticket")]
+    /// opens the ticket matching the current branch name
+    Ticket {
+        #[structopt(short = "a", long = "all")]
+        /// list the tickets checked out across all the repos instead
+        all: bool,
+    },
 
     #[structopt(external_subcommand)]
     /// run any valid command in ./.pndev any argument will be passed verbatim
     Other(Vec<String>),
 }
 
+#[derive(StructOpt, Debug)]
+enum WorkspaceCommand {
+    #[structopt(name = "save")]
+    /// record branch and commit of every cloned repo
+    Save {
+        /// snapshot name, or path to a .toml file to share it
+        name: String,
+    },
+
+    #[structopt(name = "restore")]
+    /// check every repo out to the revisions in a snapshot
+    Restore {
+        /// snapshot name, or path to a .toml file
+        name: String,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum HooksCommand {
+    #[structopt(name = "install")]
+    /// install or update the catalog hooks (and pndev.toml overrides) in every repo
+    Install {
+        #[structopt(short = "f", long = "force")]
+        /// replace existing hooks not installed by pndev
+        force: bool,
+    },
+
+    #[structopt(name = "status")]
+    /// show which repos have missing or outdated hooks
+    Status,
+}
+
 // CLI definition
 #[derive(Debug, StructOpt)]
 struct Cli {
@@ -188,6 +432,10 @@ struct Cli {
     #[structopt(flatten)]
     log: opt_log::Log,
 
+    #[structopt(long = "no-docker")]
+    /// run a ./.pndev script or pndev.toml task without starting the docker services
+    no_docker: bool,
+
     #[structopt(subcommand)]
     command: CliCommand,
 }
@@ -203,24 +451,109 @@ fn main() -> Result<(), ExitFailure> {
         CliCommand::Prepare { big } => Command::prepare(big),
         CliCommand::Shell { command } => Command::shell(command),
         CliCommand::Sh { command } => Command::shell(command),
+        CliCommand::Test { paths, junit } => Command::test(paths, junit),
+        CliCommand::Watch {
+            task,
+            globs,
+            arguments,
+        } => Command::watch(task, globs, arguments),
         CliCommand::Up => Command::up(),
-        CliCommand::Start { docker } => Command::start(docker),
+        CliCommand::Start {
+            docker,
+            explain,
+            apps,
+            stack,
+            tmux,
+            open,
+        } => {
+            if apps.is_empty() && stack.is_none() {
+                Command::start(docker, explain, open)
+            } else {
+                Command::start_apps(apps, stack, tmux, open)
+            }
+        }
         CliCommand::Down => Command::down(),
         CliCommand::Ps => Command::ps(),
         CliCommand::Reset { reset_type } => Command::reset(reset_type),
-        CliCommand::Doctor => check::doctor(),
-        CliCommand::Clone { name, all } => Command::clone(name, all),
-        CliCommand::Review { pr, name } => Command::review(pr, name),
+        CliCommand::Doctor { fix, json, bundle } => check::doctor(fix, json, bundle),
+        CliCommand::Clone {
+            name,
+            all,
+            depth,
+            filter,
+            cache,
+            no_cache,
+            https,
+            no_https,
+            bootstrap,
+        } => Command::clone(
+            name,
+            all,
+            git::CloneOptions {
+                depth,
+                filter,
+                cache: (cache || no_cache).then_some(cache),
+                https: (https || no_https).then_some(https),
+            },
+            bootstrap,
+        ),
+        CliCommand::Review {
+            pr,
+            name,
+            done,
+            delete,
+            worktree,
+            stash,
+            skip_dirty,
+        } => {
+            if done {
+                Command::review_done(pr, delete)
+            } else {
+                Command::review(pr, name, worktree, stash, skip_dirty)
+            }
+        }
+        CliCommand::Branch { branch, repos } => Command::branch(branch, repos),
+        CliCommand::Checkout { branch, repos } => Command::checkout(branch, repos),
+        CliCommand::Workspace(WorkspaceCommand::Save { name }) => Command::workspace_save(name),
+        CliCommand::Workspace(WorkspaceCommand::Restore { name }) => {
+            Command::workspace_restore(name)
+        }
+        CliCommand::Hooks(HooksCommand::Install { force }) => Command::hooks_install(force),
+        CliCommand::Hooks(HooksCommand::Status) => Command::hooks_status(),
         CliCommand::Update => update::run(),
         CliCommand::Rebuild => {
             println!("rebuild is DEPRECATED, use `pndev reset docker` instead");
             Command::reset(ResetType::Docker)
         }
-        CliCommand::Gh => Command::gh(),
+        CliCommand::Gh {
+            file,
+            branch,
+            pr,
+            actions,
+        } => {
+            let page = file.map_or_else(
+                || {
+                    if branch {
+                        git::Page::Branch
+                    } else if pr {
+                        git::Page::PullRequest
+                    } else if actions {
+                        git::Page::Actions
+                    } else {
+                        git::Page::Repo
+                    }
+                },
+                git::Page::File,
+            );
+
+            Command::gh(page)
+        }
+        CliCommand::Status => Command::status(),
+        CliCommand::Ticket { all } => Command::ticket(all),
         CliCommand::Other(list) => {
             let name = &list[0];
             let arguments = &list[1..];
-            Command::run(Some(name.into()), Vec::from(arguments))
+            Command::run(Some(name.into()), Vec::from(arguments), args.no_docker)
         }
     };
 
@@ -2,9 +2,19 @@
 // adapted to work with modern crates
 use failure::Error;
 use log::Level;
+use std::fs::{self, File, OpenOptions, Permissions};
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::sync::Mutex;
 
 use structopt::StructOpt;
 
+use crate::bundle;
+use crate::config;
+
+/// The log file is started over past this size
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+
 /// Add log functionality to Structopt.
 #[derive(StructOpt, Debug)]
 pub struct Log {
@@ -19,17 +29,64 @@ fn init_builder(_pretty: bool) -> LoggerBuilder {
     LoggerBuilder::new()
 }
 
+/// ~/.pndev.log, opened for appending and readable only by the user
+fn log_file() -> Option<File> {
+    let path = config::Config::log_path();
+    let too_big = fs::metadata(&path).is_ok_and(|metadata| metadata.len() > MAX_LOG_SIZE);
+
+    let file = OpenOptions::new()
+        .create(true)
+        .append(!too_big)
+        .write(true)
+        .truncate(too_big)
+        .mode(0o600)
+        .open(path)
+        .ok()?;
+
+    // logs written by older versions were world readable
+    file.set_permissions(Permissions::from_mode(0o600)).ok();
+
+    Some(file)
+}
+
 impl Log {
     /// Initialize `env_logger` and set the log level for all packages. No
     /// additional filtering is applied.
+    ///
+    /// What is logged also goes to ~/.pndev.log, at the same level.
     pub fn log_all(&self, level: Option<Level>) -> Result<(), Error> {
         let level_filter = match level {
             Some(level) => level.to_level_filter(),
             None => return Ok(()),
         };
+        let file = log_file().map(Mutex::new);
 
         init_builder(self.pretty)
             .filter(None, level_filter)
+            .format(move |buf, record| {
+                if let Some(file) = &file {
+                    if let Ok(mut file) = file.lock() {
+                        writeln!(
+                            file,
+                            "[{} {:<5} {}] {}",
+                            buf.timestamp(),
+                            record.level(),
+                            record.target(),
+                            bundle::redact(&record.args().to_string())
+                        )
+                        .ok();
+                    }
+                }
+
+                writeln!(
+                    buf,
+                    "[{} {:<5} {}] {}",
+                    buf.timestamp(),
+                    buf.default_styled_level(record.level()),
+                    record.target(),
+                    record.args()
+                )
+            })
             .try_init()?;
         Ok(())
     }
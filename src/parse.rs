@@ -1,5 +1,6 @@
-use failure::Error;
+use failure::{bail, Error};
 use std::fs;
+use std::path::Path;
 use toml::Value;
 
 pub fn config() -> Result<Value, Error> {
@@ -8,3 +9,88 @@ pub fn config() -> Result<Value, Error> {
 
     Ok(package_info)
 }
+
+/// pndev.toml of a project other than the current one
+pub fn config_at(dir: &Path) -> Result<Value, Error> {
+    let toml_content = fs::read_to_string(dir.join("pndev.toml"))?;
+    let package_info: Value = toml::from_str(&toml_content)?;
+
+    Ok(package_info)
+}
+
+/// A pndev.toml task, `name = "cmd"` or, to configure `pndev watch`,
+/// `name = { cmd = "cmd", watch = ["app/**/*.rb"], ignore = ["tmp"] }`
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Task {
+    pub cmd: String,
+    /// globs of the files `pndev watch` restarts the task on
+    pub watch: Vec<String>,
+    pub ignore: Vec<String>,
+}
+
+pub fn task(config: &Value, name: &str) -> Result<Option<Task>, Error> {
+    let task = match config.get(name) {
+        Some(task) => task,
+        None => return Ok(None),
+    };
+
+    if let Some(cmd) = task.as_str() {
+        return Ok(Some(Task {
+            cmd: cmd.to_owned(),
+            ..Task::default()
+        }));
+    }
+
+    let cmd = match task.get("cmd").and_then(Value::as_str) {
+        Some(cmd) => cmd,
+        None => bail!("Invalid {} command", name),
+    };
+
+    let globs = |key: &str| -> Vec<String> {
+        task.get(key)
+            .and_then(Value::as_array)
+            .map(|globs| {
+                globs
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    Ok(Some(Task {
+        cmd: cmd.to_owned(),
+        watch: globs("watch"),
+        ignore: globs("ignore"),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tasks() {
+        let config: Value = toml::from_str(
+            r#"
+            lint = "yarn lint"
+            sidekiq = { cmd = "bundle exec sidekiq", watch = ["app/**/*.rb"], ignore = ["log"] }
+            broken = { watch = ["*.rb"] }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(task(&config, "lint").unwrap().unwrap().cmd, "yarn lint");
+        assert_eq!(
+            task(&config, "sidekiq").unwrap(),
+            Some(Task {
+                cmd: "bundle exec sidekiq".to_owned(),
+                watch: vec!["app/**/*.rb".to_owned()],
+                ignore: vec!["log".to_owned()],
+            })
+        );
+        assert!(task(&config, "broken").is_err());
+        assert_eq!(task(&config, "missing").unwrap(), None);
+    }
+}
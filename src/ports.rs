@@ -0,0 +1,548 @@
+use ansi_term::Colour::{Red, Yellow};
+use failure::{bail, Error};
+use log::trace;
+use serde_yaml::value::{Tag, TaggedValue};
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use crate::check;
+use crate::config::{self, PortOverride};
+
+/// `!override` replaces the ports of a service instead of adding to them
+pub const OVERRIDE_COMPOSE_VERSION: &str = "2.24";
+
+/// Loopback listeners answer right away
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// A host port published by a compose service
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    pub service: String,
+    pub host_port: u16,
+    pub container_port: u16,
+}
+
+/// A published port something else already listens on
+#[derive(Debug)]
+pub struct Conflict {
+    pub mapping: Mapping,
+    /// process holding the port, when lsof can tell
+    pub owner: Option<String>,
+}
+
+/// Host ports published by the services of a compose file
+pub fn mappings(compose_path: &str) -> Result<Vec<Mapping>, Error> {
+    let content = fs::read_to_string(compose_path)?;
+    let compose: Value = serde_yaml::from_str(&content)?;
+
+    let services = match compose.get("services").and_then(Value::as_mapping) {
+        Some(services) => services,
+        None => bail!("No services in {}", compose_path),
+    };
+
+    let mut mappings = vec![];
+
+    for (name, service) in services {
+        let name = match name.as_str() {
+            Some(name) => name,
+            None => continue,
+        };
+
+        let ports = match service.get("ports").and_then(Value::as_sequence) {
+            Some(ports) => ports,
+            None => continue,
+        };
+
+        for port in ports {
+            if let Some((host_port, container_port)) = parse_port(port) {
+                mappings.push(Mapping {
+                    service: name.to_owned(),
+                    host_port,
+                    container_port,
+                });
+            }
+        }
+    }
+
+    Ok(mappings)
+}
+
+/// Moves the host ports of `mappings` according to the `[ports]` of the config
+fn apply_overrides(
+    mut mappings: Vec<Mapping>,
+    overrides: &BTreeMap<String, PortOverride>,
+) -> Result<Vec<Mapping>, Error> {
+    for (service, port_override) in overrides {
+        let published: Vec<&mut Mapping> = mappings
+            .iter_mut()
+            .filter(|mapping| &mapping.service == service)
+            .collect();
+
+        if published.is_empty() {
+            bail!(
+                "No {} service publishing ports in docker-compose.yml",
+                service
+            );
+        }
+
+        match port_override {
+            PortOverride::Port(port) => {
+                if published.len() > 1 {
+                    bail!(
+                        "{} publishes several ports, set one per container port, e.g. {} = {{ {} = {} }}",
+                        service,
+                        service,
+                        published[0].container_port,
+                        port
+                    );
+                }
+
+                for mapping in published {
+                    mapping.host_port = *port;
+                }
+            }
+            PortOverride::Ports(ports) => {
+                let mut published = published;
+
+                for (container_port, port) in ports {
+                    let container_port: u16 = container_port.parse()?;
+
+                    match published
+                        .iter_mut()
+                        .find(|mapping| mapping.container_port == container_port)
+                    {
+                        Some(mapping) => mapping.host_port = *port,
+                        None => bail!("{} does not publish port {}", service, container_port),
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(mappings)
+}
+
+/// Published ports of the pndev services, with the `[ports]` overrides applied
+pub fn effective() -> Result<Vec<Mapping>, Error> {
+    let config = config::Config::new();
+
+    apply_overrides(mappings(&config.docker_compose_path())?, &config.ports())
+}
+
+/// `-f` arguments for docker-compose: the catalog compose file, and the
+/// override generated from `[ports]` when ports are remapped
+pub fn compose_files() -> Result<Vec<String>, Error> {
+    if !config::Config::new().ports().is_empty() {
+        write_override()?;
+    }
+
+    Ok(current_compose_files())
+}
+
+/// Like `compose_files`, with the override as last written: for diagnostics
+/// that must not regenerate it or run docker-compose to check its version
+pub fn current_compose_files() -> Vec<String> {
+    let config = config::Config::new();
+    let mut files = vec![String::from("-f"), config.docker_compose_path()];
+    let override_path = config::Config::docker_compose_override_path();
+
+    if !config.ports().is_empty() && Path::new(&override_path).exists() {
+        files.push(String::from("-f"));
+        files.push(override_path);
+    }
+
+    files
+}
+
+/// Rewrites the override only when `[ports]` or the catalog compose file changed it
+fn write_override() -> Result<(), Error> {
+    let overridden = config::Config::new().ports();
+    let content = override_yaml(&effective()?, &overridden)?;
+    let path = config::Config::docker_compose_override_path();
+
+    if fs::read_to_string(&path).is_ok_and(|current| current == content) {
+        return Ok(());
+    }
+
+    let output = Command::new("docker-compose")
+        .args(["version", "--short"])
+        .output()?;
+    let version = check::extract_version(&String::from_utf8_lossy(&output.stdout));
+
+    if !version
+        .is_some_and(|version| check::compare_versions(&version, OVERRIDE_COMPOSE_VERSION).is_ge())
+    {
+        bail!(
+            "[ports] in ~/.pndev_config.toml needs docker-compose {} or newer",
+            OVERRIDE_COMPOSE_VERSION
+        );
+    }
+
+    trace!("writing compose override {}", content);
+    fs::write(path, content)?;
+
+    Ok(())
+}
+
+/// Compose file replacing the ports of the overridden services
+fn override_yaml(
+    mappings: &[Mapping],
+    overridden: &BTreeMap<String, PortOverride>,
+) -> Result<String, Error> {
+    let mut services = serde_yaml::Mapping::new();
+
+    for service in overridden.keys() {
+        // long syntax, `5432:5432` can read as a base 60 number in YAML 1.1
+        let ports = mappings
+            .iter()
+            .filter(|mapping| &mapping.service == service)
+            .map(|mapping| {
+                let mut port = serde_yaml::Mapping::new();
+                port.insert(Value::from("target"), Value::from(mapping.container_port));
+                port.insert(Value::from("published"), Value::from(mapping.host_port));
+                Value::Mapping(port)
+            })
+            .collect();
+
+        let mut definition = serde_yaml::Mapping::new();
+        definition.insert(
+            Value::from("ports"),
+            Value::Tagged(Box::new(TaggedValue {
+                tag: Tag::new("override"),
+                value: Value::Sequence(ports),
+            })),
+        );
+
+        services.insert(Value::from(service.as_str()), Value::Mapping(definition));
+    }
+
+    let mut compose = serde_yaml::Mapping::new();
+    compose.insert(Value::from("services"), Value::Mapping(services));
+
+    Ok(format!(
+        "# generated by pndev from [ports] in ~/.pndev_config.toml\n{}",
+        serde_yaml::to_string(&compose)?
+    ))
+}
+
+/// `PNDEV_<SERVICE>_PORT` with the first host port of each service, and
+/// `PNDEV_<SERVICE>_<CONTAINER PORT>_PORT` for every published port,
+/// so apps run in nix-shell connect to the remapped ports
+pub fn env() -> Vec<(String, String)> {
+    let mappings = match effective() {
+        Ok(mappings) => mappings,
+        Err(error) => {
+            trace!("no port env: {}", error);
+            return vec![];
+        }
+    };
+
+    let mut env = vec![];
+
+    for mapping in &mappings {
+        let service = mapping.service.to_uppercase().replace('-', "_");
+        let port = mapping.host_port.to_string();
+
+        let first = mappings
+            .iter()
+            .find(|other| other.service == mapping.service)
+            .is_some_and(|first| first == mapping);
+
+        if first {
+            env.push((format!("PNDEV_{service}_PORT"), port.clone()));
+        }
+
+        env.push((
+            format!("PNDEV_{service}_{}_PORT", mapping.container_port),
+            port,
+        ));
+    }
+
+    env
+}
+
+/// Host and container port of a `ports` entry, either the short syntax
+/// (`"5432:5432"`, `"127.0.0.1:80:80"`, `"8080:80/tcp"`) or the long one
+/// (`{ target: 80, published: 8080 }`). Entries without a host port, or with
+/// a range, publish nothing pndev can check.
+fn parse_port(port: &Value) -> Option<(u16, u16)> {
+    if let Some(long) = port.as_mapping() {
+        let number = |key: &str| -> Option<u16> {
+            let value = long.get(key)?;
+
+            value
+                .as_u64()
+                .and_then(|number| u16::try_from(number).ok())
+                .or_else(|| value.as_str()?.parse().ok())
+        };
+
+        return Some((number("published")?, number("target")?));
+    }
+
+    let short = port.as_str()?;
+    let short = short.split('/').next()?;
+    let mut parts = short.rsplit(':');
+
+    let container_port = parts.next()?.parse().ok()?;
+    let host_port = parts.next()?.parse().ok()?;
+
+    Some((host_port, container_port))
+}
+
+/// Whether something already listens on `port`. Binding privileged ports
+/// fails with a permission error for regular users, that is not a conflict.
+/// macOS lets the wildcard bind succeed next to a listener on a loopback
+/// address, so those are also probed by connecting to them.
+fn in_use(port: u16) -> bool {
+    let bound = match TcpListener::bind(("0.0.0.0", port)) {
+        Ok(_) => false,
+        Err(error) => error.kind() == ErrorKind::AddrInUse,
+    };
+
+    bound
+        || [
+            IpAddr::from(Ipv4Addr::LOCALHOST),
+            IpAddr::from(Ipv6Addr::LOCALHOST),
+        ]
+        .iter()
+        .any(|&ip| TcpStream::connect_timeout(&SocketAddr::new(ip, port), CONNECT_TIMEOUT).is_ok())
+}
+
+/// Command and pid of the process listening on `port`
+fn owner(port: u16) -> Option<String> {
+    let output = Command::new("lsof")
+        .args(["-nP", &format!("-iTCP:{port}"), "-sTCP:LISTEN", "-Fpc"])
+        .output()
+        .ok()?;
+
+    parse_lsof(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// First process of `lsof -F pc` output, `p<pid>` then `c<command>` lines
+fn parse_lsof(output: &str) -> Option<String> {
+    let mut pid = None;
+
+    for line in output.lines() {
+        match line.split_at_checked(1) {
+            Some(("p", value)) => pid = Some(value),
+            Some(("c", command)) => {
+                return Some(pid.map_or_else(
+                    || command.to_owned(),
+                    |pid| format!("{command} (pid {pid})"),
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Services of the compose file that are up, their ports are held by docker itself
+fn running_services() -> Vec<String> {
+    let output = Command::new("docker-compose")
+        .args(current_compose_files())
+        .args(["ps", "--services", "--filter", "status=running"])
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_owned)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Published ports of the pndev services already taken by another process
+pub fn conflicts() -> Result<Vec<Conflict>, Error> {
+    let bound: Vec<Mapping> = effective()?
+        .into_iter()
+        .filter(|mapping| in_use(mapping.host_port))
+        .collect();
+
+    if bound.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let running = running_services();
+    trace!("running services {:?}", running);
+
+    Ok(bound
+        .into_iter()
+        .filter(|mapping| !running.contains(&mapping.service))
+        .map(|mapping| Conflict {
+            owner: owner(mapping.host_port),
+            mapping,
+        })
+        .collect())
+}
+
+/// What to do about a conflict
+pub fn suggestion(conflict: &Conflict) -> String {
+    let port = conflict.mapping.host_port;
+
+    let free = match (port, &conflict.owner) {
+        (5432, _) => String::from(
+            "stop the local postgres, e.g. `brew services stop postgresql`, or `pndev down` in the project using it",
+        ),
+        (6379, _) => String::from(
+            "stop the local redis, e.g. `brew services stop redis`, or `pndev down` in the project using it",
+        ),
+        (_, Some(owner)) => format!("stop {owner}, or `pndev down` in the project using port {port}"),
+        (_, None) => format!("free port {port}, `lsof -nP -iTCP:{port} -sTCP:LISTEN` shows who holds it"),
+    };
+
+    format!(
+        "{free}, or move {service} with `{service} = {other}` in [ports] of ~/.pndev_config.toml",
+        service = conflict.mapping.service,
+        other = port.saturating_add(1)
+    )
+}
+
+pub fn describe(conflict: &Conflict) -> String {
+    format!(
+        "port {} for {} already in use{}",
+        conflict.mapping.host_port,
+        conflict.mapping.service,
+        conflict
+            .owner
+            .as_ref()
+            .map_or_else(String::new, |owner| format!(" by {owner}"))
+    )
+}
+
+/// Stops before docker-compose up when it would fail on a taken port,
+/// a compose file that can't be read is left to docker-compose to report
+pub fn check() -> Result<(), Error> {
+    let conflicts = match conflicts() {
+        Ok(conflicts) => conflicts,
+        Err(error) => {
+            println!("{} could not check ports: {}", Yellow.paint("⚠"), error);
+
+            return Ok(());
+        }
+    };
+
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+
+    for conflict in &conflicts {
+        println!("{} {}", Red.paint("✗"), describe(conflict));
+        println!("  {} {}", Yellow.paint("Fix:"), suggestion(conflict));
+    }
+
+    bail!("Ports already in use, docker-compose up would fail");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_port_entries() {
+        let ports: Value = serde_yaml::from_str(
+            r#"
+            - "5432:5432"
+            - "127.0.0.1:8080:80"
+            - "1025:1025/tcp"
+            - "3000"
+            - 9000
+            - "4000-4005:4000-4005"
+            - { target: 80, published: 8081 }
+            "#,
+        )
+        .unwrap();
+
+        let parsed: Vec<Option<(u16, u16)>> = ports
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .map(parse_port)
+            .collect();
+
+        assert_eq!(
+            parsed,
+            vec![
+                Some((5432, 5432)),
+                Some((8080, 80)),
+                Some((1025, 1025)),
+                None,
+                None,
+                None,
+                Some((8081, 80)),
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_port_overrides() {
+        let mapping = |service: &str, host_port, container_port| Mapping {
+            service: service.to_owned(),
+            host_port,
+            container_port,
+        };
+        let mappings = vec![
+            mapping("postgres", 5432, 5432),
+            mapping("mailcatcher", 1080, 1080),
+            mapping("mailcatcher", 1025, 1025),
+        ];
+        let overrides: BTreeMap<String, PortOverride> = toml::from_str(
+            r#"
+            postgres = 5433
+            mailcatcher = { 1025 = 2025 }
+            "#,
+        )
+        .unwrap();
+
+        let applied = apply_overrides(mappings.clone(), &overrides).unwrap();
+
+        assert_eq!(
+            applied,
+            vec![
+                mapping("postgres", 5433, 5432),
+                mapping("mailcatcher", 1080, 1080),
+                mapping("mailcatcher", 2025, 1025),
+            ]
+        );
+        assert_eq!(
+            override_yaml(&applied, &overrides).unwrap(),
+            "# generated by pndev from [ports] in ~/.pndev_config.toml\n\
+             services:\n  mailcatcher:\n    ports: !override\n    \
+             - target: 1080\n      published: 1080\n    \
+             - target: 1025\n      published: 2025\n  \
+             postgres:\n    ports: !override\n    \
+             - target: 5432\n      published: 5433\n"
+        );
+
+        let several: BTreeMap<String, PortOverride> = toml::from_str("mailcatcher = 2025").unwrap();
+        assert!(apply_overrides(mappings, &several).is_err());
+    }
+
+    #[test]
+    fn parses_lsof_output() {
+        assert_eq!(
+            parse_lsof("p1234\ncpostgres\nf7\n").as_deref(),
+            Some("postgres (pid 1234)")
+        );
+        assert_eq!(parse_lsof(""), None);
+    }
+
+    #[test]
+    fn detects_loopback_listeners() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        assert!(in_use(port));
+
+        drop(listener);
+        assert!(!in_use(port));
+    }
+}
@@ -0,0 +1,530 @@
+use crate::detect;
+use crate::parse;
+use crate::ports;
+use crate::shell;
+use ansi_term::Colour::{self, Blue, Cyan, Green, Purple, Red, Yellow};
+use failure::{bail, Error};
+use log::trace;
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const COLOURS: &[Colour] = &[Cyan, Purple, Blue, Green, Yellow, Red];
+
+/// A process crashing more often than this in a row is not restarted anymore
+const MAX_RESTARTS: u32 = 5;
+
+/// Processes running longer than this before exiting are not considered crashing
+const CRASH_WINDOW: Duration = Duration::from_secs(10);
+
+/// How long processes get to exit after SIGTERM before being killed
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// When a process is restarted after exiting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Restart {
+    Never,
+    OnFailure,
+    Always,
+}
+
+impl Restart {
+    fn parse(value: &str) -> Result<Self, Error> {
+        match value {
+            "never" => Ok(Self::Never),
+            "on-failure" => Ok(Self::OnFailure),
+            "always" => Ok(Self::Always),
+            _ => bail!(
+                "Invalid restart policy {}, use never, on-failure or always",
+                value
+            ),
+        }
+    }
+
+    fn applies(self, status: ExitStatus) -> bool {
+        match self {
+            Self::Never => false,
+            Self::OnFailure => !status.success(),
+            Self::Always => true,
+        }
+    }
+}
+
+/// A long running process started by `pndev start`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Process {
+    pub name: String,
+    /// run inside nix-shell
+    pub cmd: String,
+    pub restart: Restart,
+    /// where to run it, the current dir by default
+    pub dir: Option<PathBuf>,
+}
+
+/// Processes declared in the `[processes]` table of pndev.toml, or else in a Procfile:
+///
+/// ```toml
+/// [processes]
+/// rails = "bundle exec rails server"
+/// sidekiq = { cmd = "bundle exec sidekiq", restart = "on-failure" }
+/// ```
+pub fn load(dir: &Path) -> Result<Vec<Process>, Error> {
+    if dir.join("pndev.toml").exists() {
+        if let Some(processes) = parse::config_at(dir)?.get("processes") {
+            return from_toml(processes);
+        }
+    }
+
+    fs::read_to_string(dir.join("Procfile"))
+        .map_or_else(|_| Ok(vec![]), |procfile| Ok(from_procfile(&procfile)))
+}
+
+fn from_toml(processes: &toml::Value) -> Result<Vec<Process>, Error> {
+    let processes = match processes.as_table() {
+        Some(processes) => processes,
+        None => bail!("Invalid processes in pndev.toml, expected a table"),
+    };
+
+    processes
+        .iter()
+        .map(|(name, process)| {
+            let (cmd, restart) = match process {
+                toml::Value::String(cmd) => (cmd.as_str(), Restart::Never),
+                toml::Value::Table(table) => {
+                    let cmd = match table.get("cmd").and_then(toml::Value::as_str) {
+                        Some(cmd) => cmd,
+                        None => bail!("Missing cmd for process {} in pndev.toml", name),
+                    };
+                    let restart = match table.get("restart").and_then(toml::Value::as_str) {
+                        Some(restart) => Restart::parse(restart)?,
+                        None => Restart::Never,
+                    };
+                    (cmd, restart)
+                }
+                _ => bail!("Invalid process {} in pndev.toml", name),
+            };
+
+            Ok(Process {
+                name: name.to_owned(),
+                cmd: cmd.to_owned(),
+                restart,
+                dir: None,
+            })
+        })
+        .collect()
+}
+
+/// `name: command` lines, as used by foreman and heroku
+fn from_procfile(procfile: &str) -> Vec<Process> {
+    procfile
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, cmd)| Process {
+            name: name.trim().to_owned(),
+            cmd: cmd.trim().to_owned(),
+            restart: Restart::Never,
+            dir: None,
+        })
+        .collect()
+}
+
+/// What `pndev start` would run in the repo of `app`, named after the app
+pub fn app_processes(app: &str, dir: &Path) -> Result<Vec<Process>, Error> {
+    let in_app = |name: String, cmd: String| Process {
+        name,
+        cmd,
+        restart: Restart::Never,
+        dir: Some(dir.to_path_buf()),
+    };
+
+    if dir.join(".pndev/start").exists() {
+        return Ok(vec![in_app(app.to_owned(), String::from("./.pndev/start"))]);
+    }
+
+    if dir.join("pndev.toml").exists() {
+        if let Some(task) = parse::task(&parse::config_at(dir)?, "start")? {
+            return Ok(vec![in_app(app.to_owned(), task.cmd)]);
+        }
+    }
+
+    let processes = load(dir)?;
+    if !processes.is_empty() {
+        return Ok(processes
+            .into_iter()
+            .map(|process| Process {
+                name: format!("{app}/{}", process.name),
+                dir: Some(dir.to_path_buf()),
+                ..process
+            })
+            .collect());
+    }
+
+    match detect::detect(dir).and_then(|detection| detection.start) {
+        Some(start) => Ok(vec![in_app(app.to_owned(), start)]),
+        None => bail!(
+            "No start command found for {}, run pndev start --explain in {}",
+            app,
+            dir.display()
+        ),
+    }
+}
+
+struct Running {
+    process: Process,
+    colour: Colour,
+    child: Child,
+    started: Instant,
+    crashes: u32,
+}
+
+/// Runs all processes with prefixed output until one of them exits for good or Ctrl-C,
+/// then stops the others. Every process gets its own process group so that the whole
+/// tree nix-shell spawns is stopped.
+pub fn supervise(processes: &[Process]) -> Result<(), Error> {
+    if processes.is_empty() {
+        bail!("No processes to start");
+    }
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler_flag = Arc::clone(&interrupted);
+    ctrlc::set_handler(move || handler_flag.store(true, Ordering::SeqCst))?;
+
+    let width = processes.iter().map(|p| p.name.len()).max().unwrap_or(0);
+
+    let mut running = vec![];
+    for (i, process) in processes.iter().enumerate() {
+        let colour = COLOURS[i % COLOURS.len()];
+
+        match spawn(process, colour, width) {
+            Ok(child) => running.push(Running {
+                process: process.clone(),
+                colour,
+                child,
+                started: Instant::now(),
+                crashes: 0,
+            }),
+            Err(error) => {
+                shutdown(&mut running);
+                return Err(error);
+            }
+        }
+    }
+
+    let result = watch(&mut running, &interrupted, width);
+    shutdown(&mut running);
+
+    result
+}
+
+/// Polls the processes, restarting them according to their policy
+fn watch(running: &mut [Running], interrupted: &AtomicBool, width: usize) -> Result<(), Error> {
+    loop {
+        if interrupted.load(Ordering::SeqCst) {
+            println!("{} stopping all processes", Yellow.paint("⚠"));
+            return Ok(());
+        }
+
+        for proc in running.iter_mut() {
+            let status = match proc.child.try_wait()? {
+                Some(status) => status,
+                None => continue,
+            };
+
+            let name = &proc.process.name;
+            trace!("process {} exited with {:?}", name, status);
+
+            if proc.started.elapsed() < CRASH_WINDOW {
+                proc.crashes += 1;
+            } else {
+                proc.crashes = 0;
+            }
+
+            if proc.process.restart.applies(status) && proc.crashes <= MAX_RESTARTS {
+                println!(
+                    "{} {} exited ({}), restarting",
+                    Yellow.paint("⚠"),
+                    name,
+                    status
+                );
+                thread::sleep(Duration::from_secs(1));
+
+                proc.child = spawn(&proc.process, proc.colour, width)?;
+                proc.started = Instant::now();
+            } else if status.success() {
+                println!(
+                    "{} {} exited, stopping all processes",
+                    Yellow.paint("⚠"),
+                    name
+                );
+                return Ok(());
+            } else {
+                bail!("{} exited ({}), all processes stopped", name, status);
+            }
+        }
+
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+fn spawn(process: &Process, colour: Colour, width: usize) -> Result<Child, Error> {
+    trace!("spawning process {:?}", process);
+
+    let mut child = shell::nix_command(&process.cmd, process.dir.as_deref())?
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .spawn()?;
+
+    let prefix = colour
+        .paint(format!("{:width$} |", process.name))
+        .to_string();
+
+    forward(child.stdout.take(), prefix.clone());
+    forward(child.stderr.take(), prefix);
+
+    Ok(child)
+}
+
+/// Prints the lines of a child stream with the process prefix
+fn forward<R: Read + Send + 'static>(stream: Option<R>, prefix: String) {
+    if let Some(stream) = stream {
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                println!("{prefix} {line}");
+            }
+        });
+    }
+}
+
+/// SIGTERM to every process group still running, SIGKILL after the timeout
+fn shutdown(running: &mut [Running]) {
+    for proc in running.iter_mut() {
+        if alive(proc) {
+            signal(&proc.child, libc::SIGTERM);
+        }
+    }
+
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+    while Instant::now() < deadline && running.iter_mut().any(alive) {
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    for proc in running.iter_mut() {
+        if alive(proc) {
+            println!(
+                "{} {} did not stop, killing it",
+                Red.paint("✗"),
+                proc.process.name
+            );
+            signal(&proc.child, libc::SIGKILL);
+            let _ = proc.child.wait();
+        }
+    }
+}
+
+/// Stops a single process group the same way
+pub fn stop(child: &mut Child) {
+    if !matches!(child.try_wait(), Ok(None)) {
+        return;
+    }
+
+    signal(child, libc::SIGTERM);
+
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+    while Instant::now() < deadline && matches!(child.try_wait(), Ok(None)) {
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    if matches!(child.try_wait(), Ok(None)) {
+        signal(child, libc::SIGKILL);
+        let _ = child.wait();
+    }
+}
+
+fn alive(proc: &mut Running) -> bool {
+    matches!(proc.child.try_wait(), Ok(None))
+}
+
+fn signal(child: &Child, signal: libc::c_int) {
+    // children are spawned as process group leaders, so their pid is the group id
+    unsafe {
+        libc::killpg(child.id() as libc::pid_t, signal);
+    }
+}
+
+const TMUX_SESSION: &str = "pndev";
+
+pub fn tmux_available() -> bool {
+    Command::new("tmux")
+        .arg("-V")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Starts a tmux session with a window per app and a pane per process, then attaches to it
+pub fn tmux(apps: &[(String, Vec<Process>)]) -> Result<(), Error> {
+    if run_tmux(&["has-session", "-t", TMUX_SESSION]).is_ok() {
+        bail!(
+            "tmux session {} already exists, attach to it with tmux attach -t {}",
+            TMUX_SESSION,
+            TMUX_SESSION
+        );
+    }
+
+    let env = ports::env();
+
+    for (i, (app, processes)) in apps.iter().enumerate() {
+        let window = format!("{TMUX_SESSION}:{app}");
+
+        for (j, process) in processes.iter().enumerate() {
+            let dir = process
+                .dir
+                .as_ref()
+                .map_or_else(String::new, |dir| dir.to_string_lossy().into_owned());
+            let cmd = pane_command(
+                &env,
+                &process.cmd,
+                &shell::nix_shell_path(process.dir.as_deref())?,
+            );
+
+            // panes run an interactive shell and get the command typed in,
+            // so it stays on screen when it exits and can be run again
+            match (i, j) {
+                (0, 0) => run_tmux(&[
+                    "new-session",
+                    "-d",
+                    "-s",
+                    TMUX_SESSION,
+                    "-n",
+                    app,
+                    "-c",
+                    &dir,
+                ])?,
+                (_, 0) => run_tmux(&["new-window", "-t", TMUX_SESSION, "-n", app, "-c", &dir])?,
+                _ => {
+                    run_tmux(&["split-window", "-t", &window, "-c", &dir])?;
+                    run_tmux(&["select-layout", "-t", &window, "tiled"])?;
+                }
+            }
+
+            run_tmux(&["send-keys", "-t", &window, &cmd, "Enter"])?;
+        }
+    }
+
+    println!("{} tmux session {} started", Green.paint("✓"), TMUX_SESSION);
+
+    // already inside tmux, attaching would nest sessions
+    let attach = if std::env::var_os("TMUX").is_some() {
+        "switch-client"
+    } else {
+        "attach-session"
+    };
+
+    shell::Shell::new()
+        .cmd("tmux")
+        .args(vec![attach, "-t", TMUX_SESSION])
+        .error_msg("tmux attach failed")
+        .spawn()?;
+
+    Ok(())
+}
+
+fn run_tmux(args: &[&str]) -> Result<(), Error> {
+    trace!("tmux {:?}", args);
+
+    let output = Command::new("tmux").args(args).output()?;
+
+    if !output.status.success() {
+        bail!(
+            "tmux {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+/// Single quotes for sh
+/// What gets typed into a pane: the process in nix-shell, with the port
+/// variables pndev sets for its own nix-shells. `env` works whatever the shell.
+fn pane_command(env: &[(String, String)], cmd: &str, nix_shell_path: &str) -> String {
+    let env: String = env
+        .iter()
+        .map(|(key, value)| format!("{} ", quote(&format!("{key}={value}"))))
+        .collect();
+
+    format!(
+        "env {}nix-shell --run {} {}",
+        env,
+        quote(cmd),
+        quote(nix_shell_path)
+    )
+}
+
+fn quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_processes_table() {
+        let toml: toml::Value = toml::from_str(
+            r#"
+            [processes]
+            rails = "bundle exec rails server"
+            sidekiq = { cmd = "bundle exec sidekiq", restart = "on-failure" }
+            "#,
+        )
+        .unwrap();
+
+        let processes = from_toml(&toml["processes"]).unwrap();
+
+        assert_eq!(processes.len(), 2);
+        assert_eq!(processes[0].name, "rails");
+        assert_eq!(processes[0].restart, Restart::Never);
+        assert_eq!(processes[1].cmd, "bundle exec sidekiq");
+        assert_eq!(processes[1].restart, Restart::OnFailure);
+    }
+
+    #[test]
+    fn parses_procfile() {
+        let processes = from_procfile(
+            "# comment\n\
+             web: bundle exec rails server -p 3000\n\
+             \n\
+             webpacker: bin/webpack-dev-server\n",
+        );
+
+        assert_eq!(processes.len(), 2);
+        assert_eq!(processes[0].name, "web");
+        assert_eq!(processes[0].cmd, "bundle exec rails server -p 3000");
+        assert_eq!(processes[1].name, "webpacker");
+    }
+
+    #[test]
+    fn passes_port_variables_to_panes() {
+        let env = vec![(String::from("PNDEV_POSTGRES_PORT"), String::from("5433"))];
+
+        assert_eq!(
+            pane_command(&env, "bundle exec rails s", "/home/me/shell.nix"),
+            "env 'PNDEV_POSTGRES_PORT=5433' nix-shell --run 'bundle exec rails s' '/home/me/shell.nix'"
+        );
+        assert_eq!(
+            pane_command(&[], "yarn start", "shell.nix"),
+            "env nix-shell --run 'yarn start' 'shell.nix'"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use ansi_term::Colour::{Green, Red};
+use failure::Error;
+use regex::Regex;
+use std::fs;
+
+/// A failed test, as reported by rspec or a TAP producer (ember test)
+#[derive(Debug, PartialEq, Eq)]
+pub struct Failure {
+    pub name: String,
+    /// file:line, rspec only
+    pub location: Option<String>,
+}
+
+/// Results collected from the test runner output
+#[derive(Debug, Default)]
+pub struct Report {
+    pub tests: usize,
+    pub skipped: usize,
+    pub passed: Vec<String>,
+    pub failures: Vec<Failure>,
+}
+
+impl Report {
+    /// Collects results from rspec and TAP output, other lines are ignored.
+    ///
+    /// rspec only names failed examples (`rspec ./spec/a_spec.rb:12 # description`),
+    /// totals come from its `N examples, M failures` summary.
+    pub fn parse(lines: &[String]) -> Self {
+        let ansi = Regex::new(r"\x1b\[[0-9;]*m").unwrap();
+        let tap =
+            Regex::new(r"^(not )?ok \d+\s*(?:-\s*)?(.*?)(\s+#\s*(?i:skip|todo).*)?$").unwrap();
+        let rspec_failure = Regex::new(r"^rspec (\S+) # (.*)$").unwrap();
+        let rspec_summary =
+            Regex::new(r"^(\d+) examples?, (\d+) failures?(?:, (\d+) pending)?").unwrap();
+
+        let mut report = Self::default();
+
+        for line in lines.iter().map(|line| ansi.replace_all(line, "")) {
+            let line = line.trim_end();
+
+            if let Some(caps) = rspec_failure.captures(line) {
+                report.failures.push(Failure {
+                    name: caps[2].to_owned(),
+                    location: Some(caps[1].to_owned()),
+                });
+            } else if let Some(caps) = rspec_summary.captures(line) {
+                report.tests += caps[1].parse::<usize>().unwrap_or_default();
+                report.skipped += caps
+                    .get(3)
+                    .map_or(0, |pending| pending.as_str().parse().unwrap_or_default());
+            } else if let Some(caps) = tap.captures(line) {
+                let name = caps[2].to_owned();
+                report.tests += 1;
+
+                if caps.get(3).is_some() {
+                    report.skipped += 1;
+                } else if caps.get(1).is_some() {
+                    report.failures.push(Failure {
+                        name,
+                        location: None,
+                    });
+                } else {
+                    report.passed.push(name);
+                }
+            }
+        }
+
+        report
+    }
+
+    /// Writes a JUnit XML summary, rspec passes are counted but not listed
+    pub fn write_junit(&self, path: &str) -> Result<(), Error> {
+        fs::write(path, self.junit())?;
+
+        println!("{} JUnit report written to {}", Green.paint("✓"), path);
+
+        Ok(())
+    }
+
+    fn junit(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+
+        xml.push_str(&format!(
+            "<testsuites tests=\"{tests}\" failures=\"{failures}\" skipped=\"{skipped}\">\n  \
+             <testsuite name=\"pndev test\" tests=\"{tests}\" failures=\"{failures}\" skipped=\"{skipped}\">\n",
+            tests = self.tests,
+            failures = self.failures.len(),
+            skipped = self.skipped,
+        ));
+
+        for name in &self.passed {
+            xml.push_str(&format!("    <testcase name=\"{}\"/>\n", escape(name)));
+        }
+
+        for failure in &self.failures {
+            let file = failure
+                .location
+                .as_deref()
+                .map_or_else(String::new, |location| {
+                    format!(" file=\"{}\"", escape(location))
+                });
+
+            xml.push_str(&format!(
+                "    <testcase name=\"{}\"{}>\n      <failure message=\"{}\"/>\n    </testcase>\n",
+                escape(&failure.name),
+                file,
+                escape(&failure.name)
+            ));
+        }
+
+        xml.push_str("  </testsuite>\n</testsuites>\n");
+        xml
+    }
+
+    /// Concise list of failures printed after the runner output
+    pub fn print_summary(&self) {
+        if self.tests == 0 && self.failures.is_empty() {
+            return;
+        }
+
+        if self.failures.is_empty() {
+            println!("{} {} tests passed", Green.paint("✓"), self.tests);
+            return;
+        }
+
+        println!(
+            "\n{} {} of {} tests failed:",
+            Red.paint("✗"),
+            self.failures.len(),
+            self.tests
+        );
+
+        for failure in &self.failures {
+            match &failure.location {
+                Some(location) => println!("  {} {}", location, failure.name),
+                None => println!("  {}", failure.name),
+            }
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(output: &str) -> Vec<String> {
+        output.lines().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn parses_rspec_output() {
+        let report = Report::parse(&lines(
+            "..F.*\n\
+             \n\
+             5 examples, 1 failure, 1 pending\n\
+             \n\
+             Failed examples:\n\
+             \n\
+             \x1b[31mrspec ./spec/models/user_spec.rb:12\x1b[0m \x1b[36m# User validates email\x1b[0m\n",
+        ));
+
+        assert_eq!(report.tests, 5);
+        assert_eq!(report.skipped, 1);
+        assert_eq!(
+            report.failures,
+            vec![Failure {
+                name: "User validates email".to_owned(),
+                location: Some("./spec/models/user_spec.rb:12".to_owned()),
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_tap_output() {
+        let report = Report::parse(&lines(
+            "ok 1 Chrome 90.0 - [12 ms] - Acceptance | login: visits /login\n\
+             not ok 2 Chrome 90.0 - [40 ms] - Unit | Model | user: it exists\n\
+             ok 3 Chrome 90.0 - [0 ms] - Unit | pending # skip\n\
+             1..3\n",
+        ));
+
+        assert_eq!(report.tests, 3);
+        assert_eq!(report.skipped, 1);
+        assert_eq!(report.passed.len(), 1);
+        assert_eq!(
+            report.failures[0].name,
+            "Chrome 90.0 - [40 ms] - Unit | Model | user: it exists"
+        );
+    }
+
+    #[test]
+    fn escapes_junit_names() {
+        let report = Report {
+            tests: 1,
+            failures: vec![Failure {
+                name: "renders <b> & \"quotes\"".to_owned(),
+                location: None,
+            }],
+            ..Report::default()
+        };
+
+        assert!(report
+            .junit()
+            .contains("name=\"renders &lt;b&gt; &amp; &quot;quotes&quot;\""));
+    }
+}
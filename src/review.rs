@@ -0,0 +1,150 @@
+use crate::config;
+use failure::Error;
+use log::{info, trace};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+const STATE_FILE_NAME: &str = ".pndev_review.toml";
+
+/// A repository checked out for review
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub repo: String,
+    pub branch: String,
+    /// branch to go back to, not set for worktree reviews
+    #[serde(default)]
+    pub previous_branch: Option<String>,
+    /// label of the stash holding the changes found before the review
+    #[serde(default)]
+    pub stash: Option<String>,
+    /// path of the worktree created with `pndev review --worktree`
+    #[serde(default)]
+    pub worktree: Option<String>,
+}
+
+/// Reviews in progress, keyed by the PR passed to `pndev review`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    #[serde(default)]
+    reviews: BTreeMap<String, Vec<Entry>>,
+}
+
+impl State {
+    fn path() -> String {
+        format!("{}/{}", config::Config::home_path_str(), STATE_FILE_NAME)
+    }
+
+    pub fn load() -> Result<Self, Error> {
+        let path = Self::path();
+
+        if !Path::new(&path).exists() {
+            return Ok(Self::default());
+        }
+
+        info!("Loading review state from {}", path);
+        let content = fs::read_to_string(&path)?;
+
+        Ok(toml::from_str(&content)?)
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let path = Self::path();
+
+        trace!("saving review state to {}", path);
+        fs::write(&path, toml::to_string(self)?)?;
+
+        Ok(())
+    }
+
+    /// Label of the stash recorded for `repo` by the review of `pr`
+    pub fn stash(&self, pr: &str, repo: &str) -> Option<&str> {
+        self.reviews
+            .get(pr)?
+            .iter()
+            .find(|entry| entry.repo == repo)?
+            .stash
+            .as_deref()
+    }
+
+    /// Records the repos checked out for `pr`.
+    /// Repeating a review keeps the branch that was checked out the first time,
+    /// otherwise `--done` would bring you back to the review branch itself.
+    pub fn record(&mut self, pr: &str, entries: Vec<Entry>) {
+        let recorded = self.reviews.entry(pr.to_owned()).or_default();
+
+        for entry in entries {
+            match recorded.iter_mut().find(|e| e.repo == entry.repo) {
+                Some(existing) => {
+                    existing.branch = entry.branch;
+                    // a worktree review and a checkout review of the same pr both need undoing
+                    existing.previous_branch =
+                        existing.previous_branch.take().or(entry.previous_branch);
+                    existing.stash = existing.stash.take().or(entry.stash);
+                    existing.worktree = existing.worktree.take().or(entry.worktree);
+                }
+                None => recorded.push(entry),
+            }
+        }
+    }
+
+    /// Removes and returns the reviews for `pr`, or all of them
+    pub fn take(&mut self, pr: Option<&str>) -> Vec<(String, Vec<Entry>)> {
+        match pr {
+            Some(pr) => self
+                .reviews
+                .remove_entry(pr)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            None => std::mem::take(&mut self.reviews).into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(previous_branch: Option<&str>, worktree: Option<&str>) -> Entry {
+        Entry {
+            repo: "fitpro".to_owned(),
+            branch: "pr-12".to_owned(),
+            previous_branch: previous_branch.map(str::to_owned),
+            stash: None,
+            worktree: worktree.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn merges_repeated_reviews() {
+        let mut state = State::default();
+
+        state.record("#12", vec![entry(Some("master"), None)]);
+        state.record("#12", vec![entry(Some("pr-12"), None)]);
+        state.record("#12", vec![entry(None, Some("/review/pr-12/fitpro"))]);
+
+        let reviews = state.take(Some("#12"));
+        let merged = &reviews[0].1[0];
+
+        assert_eq!(merged.previous_branch.as_deref(), Some("master"));
+        assert_eq!(merged.worktree.as_deref(), Some("/review/pr-12/fitpro"));
+        assert!(state.take(None).is_empty());
+    }
+
+    #[test]
+    fn finds_recorded_stashes() {
+        let mut state = State::default();
+        let mut stashed = entry(Some("master"), None);
+        stashed.stash = Some("pndev review #12 1700000000000".to_owned());
+
+        state.record("#12", vec![stashed]);
+
+        assert_eq!(
+            state.stash("#12", "fitpro"),
+            Some("pndev review #12 1700000000000")
+        );
+        assert_eq!(state.stash("#12", "es-admin"), None);
+        assert_eq!(state.stash("#13", "fitpro"), None);
+    }
+}
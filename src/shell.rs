@@ -1,17 +1,20 @@
 use crate::config;
 use crate::git;
+use crate::ports;
 use ansi_term::Colour::Yellow;
 use failure::bail;
 use failure::Error;
 use log::info;
 use log::trace;
+use std::io::{BufRead, BufReader};
 use std::path::Path;
-use std::process::{Command, ExitStatus};
+use std::process::{Command, ExitStatus, Stdio};
 
 #[derive(Debug)]
 pub struct Shell<'a> {
     cmd: Option<String>,
     args: Vec<&'a str>,
+    envs: Vec<(String, String)>,
     error_msg: &'a str,
 }
 
@@ -30,12 +33,17 @@ impl<'a> Shell<'a> {
         self
     }
 
-    pub fn error_msg(&mut self, error_msg: &'a str) -> &mut Self {
+    pub fn envs(&mut self, envs: Vec<(String, String)>) -> &mut Self {
+        self.envs = envs;
+        self
+    }
+
+    pub const fn error_msg(&mut self, error_msg: &'a str) -> &mut Self {
         self.error_msg = error_msg;
         self
     }
 
-    pub fn spawn(&mut self) -> Result<ExitStatus, Error> {
+    pub fn spawn(&self) -> Result<ExitStatus, Error> {
         Shell::check_setup()?;
 
         let cmd = match &self.cmd {
@@ -49,7 +57,11 @@ impl<'a> Shell<'a> {
             &self.args
         );
 
-        let status = Command::new(cmd).args(&self.args).spawn()?.wait()?;
+        let status = Command::new(cmd)
+            .args(&self.args)
+            .envs(self.envs.iter().cloned())
+            .spawn()?
+            .wait()?;
 
         trace!("command {:?} executed with args {:?}", cmd, &self.args);
 
@@ -63,6 +75,62 @@ impl<'a> Shell<'a> {
         }
     }
 
+    /// Like spawn, but also collects the stdout lines while printing them.
+    /// A failed exit status is left to the caller.
+    pub fn spawn_captured(&self) -> Result<(ExitStatus, Vec<String>), Error> {
+        Shell::check_setup()?;
+
+        let cmd = match &self.cmd {
+            Some(cmd) => cmd,
+            None => bail!("missing cmd"),
+        };
+
+        trace!(
+            "attempting to capture command {:?} with args {:?}",
+            cmd,
+            &self.args
+        );
+
+        let mut child = Command::new(cmd)
+            .args(&self.args)
+            .envs(self.envs.iter().cloned())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let mut lines = vec![];
+        let mut read_error = None;
+        if let Some(stdout) = child.stdout.take() {
+            let mut reader = BufReader::new(stdout);
+            let mut buffer = vec![];
+            loop {
+                buffer.clear();
+                match reader.read_until(b'\n', &mut buffer) {
+                    Ok(0) => break,
+                    Ok(_) => {
+                        let line = String::from_utf8_lossy(&buffer);
+                        let line = line.trim_end_matches(&['\r', '\n'][..]).to_owned();
+                        println!("{line}");
+                        lines.push(line);
+                    }
+                    Err(error) => {
+                        read_error = Some(error);
+                        break;
+                    }
+                }
+            }
+        }
+
+        // reap the child even when its output couldn't be read
+        let status = child.wait()?;
+        if let Some(error) = read_error {
+            bail!("reading the output of {} failed: {}", cmd, error)
+        }
+
+        trace!("command {:?} exited with {:?}", cmd, status.code());
+
+        Ok((status, lines))
+    }
+
     pub fn check_setup() -> Result<(), Error> {
         let path = format!("{}/pndev", config::Config::new().repo_path());
 
@@ -81,6 +149,7 @@ impl Default for Shell<'_> {
         Self {
             cmd: None,
             args: vec![],
+            envs: vec![],
             error_msg: "Shell command failed",
         }
     }
@@ -95,11 +164,9 @@ pub fn docker_up_recreate() -> Result<ExitStatus, Error> {
 }
 
 fn _docker_up(force_recreate: bool) -> Result<ExitStatus, Error> {
-    let mut args = vec!["-f"];
-
-    let pndev_path = config::Config::new().docker_compose_path();
+    let files = ports::compose_files()?;
+    let mut args: Vec<&str> = files.iter().map(String::as_str).collect();
 
-    args.push(&pndev_path);
     args.extend_from_slice(&["up", "-d"]);
 
     if force_recreate {
@@ -116,11 +183,9 @@ fn _docker_up(force_recreate: bool) -> Result<ExitStatus, Error> {
 }
 
 pub fn docker_down() -> Result<ExitStatus, Error> {
-    let mut args = vec!["-f"];
-
-    let pndev_path = config::Config::new().docker_compose_path();
+    let files = ports::compose_files()?;
+    let mut args: Vec<&str> = files.iter().map(String::as_str).collect();
 
-    args.push(&pndev_path);
     args.extend_from_slice(&["down"]);
 
     Shell::new()
@@ -131,11 +196,9 @@ pub fn docker_down() -> Result<ExitStatus, Error> {
 }
 
 pub fn docker_ps() -> Result<ExitStatus, Error> {
-    let mut args = vec!["-f"];
+    let files = ports::compose_files()?;
+    let mut args: Vec<&str> = files.iter().map(String::as_str).collect();
 
-    let pndev_path = config::Config::new().docker_compose_path();
-
-    args.push(&pndev_path);
     args.extend_from_slice(&["ps"]);
 
     Shell::new()
@@ -146,11 +209,9 @@ pub fn docker_ps() -> Result<ExitStatus, Error> {
 }
 
 pub fn docker_rebuild() -> Result<ExitStatus, Error> {
-    let mut args = vec!["-f"];
-
-    let pndev_path = config::Config::new().docker_compose_path();
+    let files = ports::compose_files()?;
+    let mut args: Vec<&str> = files.iter().map(String::as_str).collect();
 
-    args.push(&pndev_path);
     args.extend_from_slice(&["build", "--no-cache"]);
 
     Shell::new()
@@ -160,13 +221,6 @@ pub fn docker_rebuild() -> Result<ExitStatus, Error> {
         .spawn()
 }
 
-pub fn ember_start() -> Result<ExitStatus, Error> {
-    let path = nix_shell_config_path()?;
-    let args = vec!["--run", "yarn && yarn exec ember server", &path];
-
-    Shell::new().cmd("nix-shell").args(args).spawn()
-}
-
 pub fn reset() -> Result<ExitStatus, Error> {
     let args2 = vec![
         "-rf",
@@ -190,23 +244,11 @@ fn nix_shell_config_path() -> Result<String, Error> {
         trace!("using shell.nix from current dir");
         Ok(String::from("shell.nix"))
     } else {
-        let args = ["rev-parse", "--show-toplevel"];
-        let result = Command::new("git").args(args).output();
-        match result {
-            Ok(output) => {
-                if output.status.code().unwrap() % 255 == 0 {
-                    let mut path = std::str::from_utf8(&output.stdout).unwrap().to_string();
-                    path.pop();
-                    path.push_str("/shell.nix");
-                    trace!("loading repo shell nix with path {:?}", path);
-                    println!("{} {}", Yellow.paint("Using:"), path);
-                    Ok(path)
-                } else {
-                    bail!("{}", std::str::from_utf8(&output.stderr).unwrap())
-                }
-            }
-            Err(err) => bail!("{} error", err),
-        }
+        let path = git::toplevel()?.join("shell.nix");
+        let path = path.to_string_lossy().into_owned();
+        trace!("loading repo shell nix with path {:?}", path);
+        println!("{} {}", Yellow.paint("Using:"), path);
+        Ok(path)
     }
 }
 
@@ -217,15 +259,55 @@ pub fn run(cmd: &str) -> Result<ExitStatus, Error> {
     Shell::new()
         .cmd("nix-shell")
         .args(args)
+        .envs(ports::env())
         .error_msg("nix-shell --run failed")
         .spawn()
 }
 
+/// Runs `cmd` in nix-shell, returning its output lines as well
+pub fn run_captured(cmd: &str) -> Result<(ExitStatus, Vec<String>), Error> {
+    let path = nix_shell_config_path()?;
+    let args = vec!["--run", cmd, &path];
+
+    Shell::new()
+        .cmd("nix-shell")
+        .args(args)
+        .envs(ports::env())
+        .spawn_captured()
+}
+
+/// shell.nix of a repo other than the current one
+pub fn nix_shell_path(dir: Option<&Path>) -> Result<String, Error> {
+    dir.map_or_else(nix_shell_config_path, |dir| {
+        Ok(dir.join("shell.nix").to_string_lossy().into_owned())
+    })
+}
+
+/// nix-shell running `cmd` in `dir` (the current dir by default),
+/// for callers that manage the child process themselves
+pub fn nix_command(cmd: &str, dir: Option<&Path>) -> Result<Command, Error> {
+    Shell::check_setup()?;
+
+    let path = nix_shell_path(dir)?;
+    let mut command = Command::new("nix-shell");
+    command.args(["--run", cmd, &path]).envs(ports::env());
+
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+
+    Ok(command)
+}
+
 pub fn nix(arguments: &str) -> Result<ExitStatus, Error> {
     if !arguments.is_empty() {
         run(arguments)
     } else {
         let path = nix_shell_config_path()?;
-        Shell::new().cmd("nix-shell").args(vec![&path]).spawn()
+        Shell::new()
+            .cmd("nix-shell")
+            .args(vec![&path])
+            .envs(ports::env())
+            .spawn()
     }
 }
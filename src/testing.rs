@@ -0,0 +1,58 @@
+use git2::{Oid, Repository, Signature};
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+/// HOME and the current dir are process wide, the tests changing them take turns
+static ENV: Mutex<()> = Mutex::new(());
+
+/// Points HOME somewhere else until dropped, then puts HOME and the current dir back
+pub struct Home {
+    home: Option<OsString>,
+    dir: PathBuf,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Home {
+    pub fn set(home: &Path) -> Self {
+        let lock = ENV.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let saved = Self {
+            home: env::var_os("HOME"),
+            dir: env::current_dir().unwrap(),
+            _lock: lock,
+        };
+
+        env::set_var("HOME", home);
+
+        saved
+    }
+}
+
+impl Drop for Home {
+    fn drop(&mut self) {
+        env::set_current_dir(&self.dir).ok();
+
+        match &self.home {
+            Some(home) => env::set_var("HOME", home),
+            None => env::remove_var("HOME"),
+        }
+    }
+}
+
+pub fn signature() -> Signature<'static> {
+    Signature::now("pndev", "pndev@example.com").unwrap()
+}
+
+/// Commits the index on top of HEAD, moving `update_ref` to the new commit
+pub fn commit(repo: &Repository, message: &str, update_ref: Option<&str>) -> Oid {
+    let signature = signature();
+    let tree = repo
+        .find_tree(repo.index().unwrap().write_tree().unwrap())
+        .unwrap();
+    let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+    let parents: Vec<_> = parent.iter().collect();
+
+    repo.commit(update_ref, &signature, &signature, message, &tree, &parents)
+        .unwrap()
+}
@@ -0,0 +1,37 @@
+use regex::Regex;
+
+/// Ticket ID found in a branch name, i.e. `DS-123` in `DS-123-fix-login`
+pub fn extract(regex: &Regex, branch: &str) -> Option<String> {
+    regex.find(branch).map(|ticket| ticket.as_str().to_owned())
+}
+
+/// Fills the `{ticket}` placeholder of the configured ticket url
+#[allow(clippy::literal_string_with_formatting_args)]
+pub fn url(template: &str, ticket: &str) -> String {
+    template.replace("{ticket}", ticket)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_ticket_from_branch() {
+        let regex = Regex::new(r"[A-Z][A-Z0-9]+-[0-9]+").unwrap();
+
+        assert_eq!(extract(&regex, "DS-123"), Some("DS-123".into()));
+        assert_eq!(
+            extract(&regex, "feature/DS-123-login"),
+            Some("DS-123".into())
+        );
+        assert_eq!(extract(&regex, "master"), None);
+    }
+
+    #[test]
+    fn builds_ticket_url() {
+        assert_eq!(
+            url("https://example.atlassian.net/browse/{ticket}", "DS-123"),
+            "https://example.atlassian.net/browse/DS-123"
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use crate::procs;
+use crate::shell;
+use ansi_term::Colour::{Green, Red, Yellow};
+use failure::{bail, Error};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use log::trace;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::fs;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Never watched, on top of the task's `ignore`
+const DEFAULT_IGNORES: &[&str] = &[
+    ".git",
+    "**/node_modules",
+    "tmp",
+    "log",
+    ".nix-gems",
+    ".nix-node",
+];
+
+/// Changes closer than this are handled together, editors write files in several steps
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Patterns match paths relative to the project dir, a pattern also ignores
+/// everything below the directories it matches
+fn globset(patterns: &[String], subtrees: bool) -> Result<GlobSet, Error> {
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in patterns {
+        let pattern = pattern.trim_end_matches('/');
+        builder.add(Glob::new(pattern)?);
+
+        if subtrees && !pattern.ends_with("/**") {
+            builder.add(Glob::new(&format!("{pattern}/**"))?);
+        }
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Runs `cmd` in nix-shell and restarts it whenever a file matching `globs`
+/// changes in the current dir, until Ctrl-C
+pub fn run(cmd: &str, globs: &[String], ignore: &[String]) -> Result<(), Error> {
+    let root = std::env::current_dir()?;
+
+    let globs = if globs.is_empty() {
+        vec![String::from("**")]
+    } else {
+        globs.to_vec()
+    };
+    let watched = globset(&globs, false)?;
+    let ignored = globset(
+        &DEFAULT_IGNORES
+            .iter()
+            .map(|&pattern| pattern.to_owned())
+            .chain(ignore.iter().cloned())
+            .collect::<Vec<_>>(),
+        true,
+    )?;
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler_flag = Arc::clone(&interrupted);
+    ctrlc::set_handler(move || handler_flag.store(true, Ordering::SeqCst))?;
+
+    let (tx, rx) = channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watch_tree(&mut watcher, &root, &root, &ignored)?;
+
+    println!("{} watching {}", Green.paint("✓"), globs.join(", "));
+
+    let mut child = Some(spawn(cmd)?);
+
+    loop {
+        if interrupted.load(Ordering::SeqCst) {
+            if let Some(child) = child.as_mut() {
+                procs::stop(child);
+            }
+            return Ok(());
+        }
+
+        if let Some(running) = child.as_mut() {
+            if let Some(status) = running.try_wait()? {
+                if status.success() {
+                    println!("{} {} finished, waiting for changes", Green.paint("✓"), cmd);
+                } else {
+                    println!(
+                        "{} {} failed ({}), waiting for changes",
+                        Red.paint("✗"),
+                        cmd,
+                        status
+                    );
+                }
+                child = None;
+            }
+        }
+
+        let changed = match next_change(&rx, &mut watcher, &root, &watched, &ignored)? {
+            Some(changed) => changed,
+            None => continue,
+        };
+
+        println!(
+            "{} {}",
+            Yellow.paint("Changed:"),
+            changed.strip_prefix(&root).unwrap_or(&changed).display()
+        );
+
+        if let Some(running) = child.as_mut() {
+            procs::stop(running);
+        }
+        child = Some(spawn(cmd)?);
+    }
+}
+
+/// Waits a bit for a relevant change, then for things to settle down
+fn next_change(
+    rx: &Receiver<notify::Result<Event>>,
+    watcher: &mut RecommendedWatcher,
+    root: &Path,
+    watched: &GlobSet,
+    ignored: &GlobSet,
+) -> Result<Option<PathBuf>, Error> {
+    let mut changed = None;
+    let mut timeout = Duration::from_millis(200);
+
+    loop {
+        let event = match rx.recv_timeout(timeout) {
+            Ok(event) => event?,
+            Err(RecvTimeoutError::Timeout) => return Ok(changed),
+            Err(RecvTimeoutError::Disconnected) => bail!("File watcher stopped"),
+        };
+
+        if matches!(event.kind, EventKind::Access(_)) {
+            continue;
+        }
+
+        for path in event.paths {
+            let relative = match path.strip_prefix(root) {
+                Ok(relative) => relative,
+                Err(_) => continue,
+            };
+
+            if ignored.is_match(relative) {
+                continue;
+            }
+
+            // new directories are not covered by the watches set up so far
+            if matches!(event.kind, EventKind::Create(_)) && path.is_dir() {
+                watch_tree(watcher, root, &path, ignored)?;
+            }
+
+            if watched.is_match(relative) && changed.is_none() {
+                trace!("change in {:?}", relative);
+                changed = Some(path.clone());
+                timeout = DEBOUNCE;
+            }
+        }
+    }
+}
+
+/// Watches `dir` and its subdirectories one by one, so that huge ignored
+/// directories like node_modules don't use up the inotify watches
+fn watch_tree(
+    watcher: &mut RecommendedWatcher,
+    root: &Path,
+    dir: &Path,
+    ignored: &GlobSet,
+) -> Result<(), Error> {
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+
+        if path.is_dir() && !path.is_symlink() && !ignored.is_match(relative) {
+            watch_tree(watcher, root, &path, ignored)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn spawn(cmd: &str) -> Result<Child, Error> {
+    trace!("starting {}", cmd);
+
+    // outside the terminal's foreground group reading the terminal would stop the task (SIGTTIN)
+    Ok(shell::nix_command(cmd, None)?
+        .process_group(0)
+        .stdin(Stdio::null())
+        .spawn()?)
+}
@@ -0,0 +1,192 @@
+use crate::config;
+use crate::git;
+use ansi_term::Colour::{Green, Yellow};
+use failure::{bail, Error};
+use log::{info, trace};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Revision of one repository in a workspace snapshot
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Revision {
+    pub repo: String,
+    /// not set when HEAD was detached
+    pub branch: Option<String>,
+    pub commit: String,
+    /// uncommitted changes are not part of the snapshot
+    pub dirty: bool,
+}
+
+/// The exact revision set of the workspace repos,
+/// stored in a lock-style file that can be shared with a teammate
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    #[serde(default)]
+    repos: Vec<Revision>,
+}
+
+/// Snapshots are stored under the install path unless `name` is a path to a toml file.
+/// Relative paths are resolved right away, git commands change the current dir.
+fn snapshot_path(name: &str) -> String {
+    if name.contains('/') || name.ends_with(".toml") {
+        std::env::current_dir().map_or_else(
+            |_| name.to_owned(),
+            |dir| dir.join(name).to_string_lossy().into_owned(),
+        )
+    } else {
+        format!(
+            "{}/.workspaces/{name}.toml",
+            config::Config::new().repo_path()
+        )
+    }
+}
+
+fn cloned(repo: &str) -> bool {
+    Path::new(&format!("{}/{repo}", config::Config::new().repo_path())).exists()
+}
+
+/// Records branch and commit of every cloned repo
+pub fn save(name: &str, repos: &[&str]) -> Result<(), Error> {
+    let path = snapshot_path(name);
+    let mut snapshot = Snapshot::default();
+
+    for &repo in repos.iter().filter(|repo| cloned(repo)) {
+        let git::Status {
+            branch,
+            commit,
+            dirty,
+            ..
+        } = git::status(repo)?;
+
+        if dirty {
+            println!(
+                "{} {} has uncommitted changes, they are not part of the snapshot",
+                Yellow.paint("⚠"),
+                repo
+            );
+        }
+
+        snapshot.repos.push(Revision {
+            repo: repo.to_owned(),
+            branch,
+            commit,
+            dirty,
+        });
+    }
+
+    if let Some(parent) = Path::new(&path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    trace!("saving workspace snapshot {:?}", snapshot);
+    fs::write(&path, toml::to_string(&snapshot)?)?;
+
+    println!("{} workspace saved to {}", Green.paint("✓"), path);
+
+    Ok(())
+}
+
+/// Checks every repo out to the recorded commits, refusing to touch dirty trees
+pub fn restore(name: &str) -> Result<(), Error> {
+    let path = snapshot_path(name);
+
+    if !Path::new(&path).exists() {
+        bail!("Workspace snapshot {} not found", path);
+    }
+
+    info!("Restoring workspace from {}", path);
+    let snapshot: Snapshot = toml::from_str(&fs::read_to_string(&path)?)?;
+
+    let mut dirty = vec![];
+    for revision in &snapshot.repos {
+        if !cloned(&revision.repo) {
+            bail!(
+                "{} is not cloned, run pndev clone {}",
+                revision.repo,
+                revision.repo
+            );
+        }
+
+        if git::is_dirty(&revision.repo)? {
+            dirty.push(revision.repo.as_str());
+        }
+    }
+
+    if !dirty.is_empty() {
+        bail!(
+            "Uncommitted changes in {}, commit or stash them first",
+            dirty.join(", ")
+        );
+    }
+
+    for revision in &snapshot.repos {
+        git::restore_revision(&revision.repo, revision.branch.as_deref(), &revision.commit)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{commit, Home};
+    use git2::{Oid, Repository};
+    use tempfile::TempDir;
+
+    /// A home with a fitpro repo at the default install path,
+    /// HOME and the current dir are put back when the `Home` is dropped
+    fn workspace() -> (Home, TempDir, Repository) {
+        let home = tempfile::tempdir().unwrap();
+        let env = Home::set(home.path());
+
+        let repo = Repository::init(home.path().join("DEV/PN/fitpro")).unwrap();
+        commit(&repo, "first", Some("HEAD"));
+
+        (env, home, repo)
+    }
+
+    fn head(repo: &Repository) -> Oid {
+        repo.head().unwrap().peel_to_commit().unwrap().id()
+    }
+
+    #[test]
+    fn restore_refuses_dirty_trees() {
+        let (_env, home, repo) = workspace();
+        let snapshot = home.path().join("snapshot.toml");
+        let snapshot = snapshot.to_str().unwrap();
+
+        save(snapshot, &["fitpro"]).unwrap();
+        let saved = head(&repo);
+
+        commit(&repo, "second", Some("HEAD"));
+        fs::write(repo.workdir().unwrap().join("notes.txt"), "wip").unwrap();
+
+        let error = restore(snapshot).unwrap_err();
+        assert!(error.to_string().contains("Uncommitted changes in fitpro"));
+        assert_ne!(head(&repo), saved);
+    }
+
+    #[test]
+    fn restores_detached_snapshots() {
+        let (_env, home, repo) = workspace();
+        let snapshot = home.path().join("snapshot.toml");
+        let snapshot = snapshot.to_str().unwrap();
+
+        let branch = repo.head().unwrap().name().unwrap().to_owned();
+        let detached = head(&repo);
+        repo.set_head_detached(detached).unwrap();
+        save(snapshot, &["fitpro"]).unwrap();
+
+        let saved: Snapshot = toml::from_str(&fs::read_to_string(snapshot).unwrap()).unwrap();
+        assert_eq!(saved.repos[0].branch, None);
+        assert_eq!(saved.repos[0].commit, detached.to_string());
+
+        repo.set_head(&branch).unwrap();
+        commit(&repo, "second", Some("HEAD"));
+
+        restore(snapshot).unwrap();
+        assert!(repo.head_detached().unwrap());
+        assert_eq!(head(&repo), detached);
+    }
+}